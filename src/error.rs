@@ -0,0 +1,141 @@
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    UnknownTrack(String),
+    StepCount {
+        expected: usize,
+        found: usize,
+    },
+    UnsupportedVersion(u32),
+    MismatchedSound {
+        track: String,
+        sound: &'static str,
+    },
+    OutOfRange {
+        param: &'static str,
+        value: f64,
+        min: f64,
+        max: f64,
+    },
+    MalformedSteps(String),
+    UntrainedTrack(String),
+    UnknownCatchUp(String),
+    UnknownUnit(String),
+    MalformedWav(String),
+    UnknownWave(String),
+    NoWave(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::UnknownTrack(name) => write!(f, "unknown track \"{}\"", name),
+            Error::StepCount { expected, found } => {
+                write!(f, "expected {} steps, found {}", expected, found)
+            }
+            Error::UnsupportedVersion(version) => {
+                write!(f, "unsupported pattern version {}", version)
+            }
+            Error::MismatchedSound { track, sound } => {
+                write!(f, "track \"{}\" cannot lock {} parameters", track, sound)
+            }
+            Error::OutOfRange {
+                param,
+                value,
+                min,
+                max,
+            } => write!(
+                f,
+                "{} must be between {} and {}, got {}",
+                param, min, max, value
+            ),
+            Error::MalformedSteps(reason) => write!(f, "malformed step data: {}", reason),
+            Error::UntrainedTrack(name) => {
+                write!(
+                    f,
+                    "no pattern model has been trained for track \"{}\"",
+                    name
+                )
+            }
+            Error::UnknownCatchUp(policy) => write!(f, "unknown catch-up policy \"{}\"", policy),
+            Error::UnknownUnit(unit) => write!(f, "unknown unit \"{}\"", unit),
+            Error::MalformedWav(reason) => write!(f, "malformed WAV file: {}", reason),
+            Error::UnknownWave(wave) => write!(f, "unknown wave \"{}\"", wave),
+            Error::NoWave(track) => write!(f, "track \"{}\" has no oscillator shape", track),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for JsValue {
+    fn from(error: Error) -> JsValue {
+        JsValue::from_str(&error.to_string())
+    }
+}
+
+pub fn check_range(param: &'static str, value: f64, min: f64, max: f64) -> Result<(), Error> {
+    if value.is_nan() || value < min || value > max {
+        return Err(Error::OutOfRange {
+            param,
+            value,
+            min,
+            max,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_range_accepts_the_bounds_and_rejects_the_rest() {
+        assert_eq!(check_range("tempo", 30.0, 30.0, 300.0), Ok(()));
+        assert_eq!(check_range("tempo", 300.0, 30.0, 300.0), Ok(()));
+        assert_eq!(
+            check_range("tempo", 301.0, 30.0, 300.0),
+            Err(Error::OutOfRange {
+                param: "tempo",
+                value: 301.0,
+                min: 30.0,
+                max: 300.0,
+            })
+        );
+        assert!(check_range("tempo", f64::NAN, 30.0, 300.0).is_err());
+        assert!(check_range("tempo", f64::INFINITY, 30.0, 300.0).is_err());
+    }
+
+    #[test]
+    fn errors_describe_the_problem() {
+        assert_eq!(
+            Error::UnknownTrack("cowbell".to_string()).to_string(),
+            "unknown track \"cowbell\""
+        );
+        assert_eq!(
+            Error::StepCount {
+                expected: 16,
+                found: 3
+            }
+            .to_string(),
+            "expected 16 steps, found 3"
+        );
+        assert_eq!(
+            Error::MismatchedSound {
+                track: "kick".to_string(),
+                sound: "snare"
+            }
+            .to_string(),
+            "track \"kick\" cannot lock snare parameters"
+        );
+        assert_eq!(
+            check_range("kick volume", 2.0, 0.0, 1.0)
+                .unwrap_err()
+                .to_string(),
+            "kick volume must be between 0 and 1, got 2"
+        );
+    }
+}
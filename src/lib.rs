@@ -1,16 +1,53 @@
 mod utils;
 
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::{HashMap, VecDeque};
 use wasm_bindgen::prelude::*;
 use web_sys::AudioContext;
 
-mod sounds;
-use sounds::hat::{Hat, HatParams, HatValues};
-use sounds::kick::{Kick, KickParams, KickValues};
-use sounds::snare::{Snare, SnareParams, SnareValues};
-use sounds::{wave_string_to_osc, SoundParams};
+pub mod error;
+use error::{check_range, Error};
+
+pub mod sounds;
+use sounds::clap::{Clap, ClapParams};
+use sounds::cowbell::{Cowbell, CowbellParams};
+use sounds::cymbal::{Cymbal, CymbalParams};
+use sounds::fm::{Fm, FmParams};
+use sounds::hat::{Hat, HatParams};
+use sounds::kick::{Kick, KickParams};
+use sounds::rimshot::{Rimshot, RimshotParams};
+use sounds::sampler::{Sampler, SamplerParams};
+use sounds::snare::{Snare, SnareParams};
+use sounds::tom::{Tom, TomParams};
+use sounds::wave::Wave;
+use sounds::SoundParams;
 
 mod sequencer;
-use sequencer::{get_sequencer_steps, Sequencer, Trigger};
+use sequencer::{
+    get_sequencer_steps, release_events, CatchUp, Humanize, Offset, ScheduleStats, Scheduling,
+    Sequencer, StepEvent,
+};
+
+pub mod step;
+use step::{Pattern, Sequence, PATTERN_SCHEMA};
+
+mod mutate;
+use mutate::{MutationSettings, Mutator};
+
+pub mod markov;
+use markov::MarkovModel;
+
+pub mod morph;
+use morph::Morph;
+
+mod transport;
+use transport::{Transport, STEPS_PER_BEAT};
+
+mod metronome;
+use metronome::Metronome;
+
+const EVENT_QUEUE_LIMIT: usize = 1024;
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
@@ -21,44 +58,71 @@ static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 #[wasm_bindgen]
 pub struct Audio {
     ctx: AudioContext,
-    schedule_interval: f32,
-    kick_sequencer: Sequencer,
-    snare_sequencer: Sequencer,
-    hat_sequencer: Sequencer,
-    tempo: f32,
+    scheduling: Scheduling,
+    sequencers: Vec<Sequencer>,
+    transport: Transport,
+    metronome: Metronome,
+    mutator: Option<Mutator>,
+    markov_models: HashMap<String, MarkovModel>,
+    morphs: HashMap<String, Morph>,
+    events: VecDeque<StepEvent>,
+    pending_events: Vec<StepEvent>,
+    event_callback: Option<js_sys::Function>,
 }
 
 #[wasm_bindgen]
 impl Audio {
     #[wasm_bindgen(constructor)]
     pub fn new() -> Result<Audio, JsValue> {
+        utils::set_panic_hook();
         let ctx = web_sys::AudioContext::new()?;
-        let kick = Kick::new(&ctx)?;
-        let mut kick_sequencer = Sequencer::new(90.0, Box::new(kick));
-        kick_sequencer.sequence = Default::default();
-        let snare = Snare::new(&ctx)?;
-        let mut snare_sequencer = Sequencer::new(90.0, Box::new(snare));
-        snare_sequencer.sequence = Default::default();
-        let hat = Hat::new(&ctx)?;
-        let mut hat_sequencer = Sequencer::new(90.0, Box::new(hat));
-        hat_sequencer.sequence = Default::default();
+        let sequencers = vec![
+            Sequencer::new(90.0, Box::new(Kick::new(&ctx)?)),
+            Sequencer::new(90.0, Box::new(Snare::new(&ctx)?)),
+            Sequencer::new(90.0, Box::new(Hat::new(&ctx)?)),
+            Sequencer::new(90.0, Box::new(Clap::new(&ctx)?)),
+            Sequencer::new(90.0, Box::new(Tom::new(&ctx)?)),
+            Sequencer::new(90.0, Box::new(Cymbal::new(&ctx)?)),
+            Sequencer::new(90.0, Box::new(Cowbell::new(&ctx)?)),
+            Sequencer::new(90.0, Box::new(Rimshot::new(&ctx)?)),
+            Sequencer::new(90.0, Box::new(Sampler::new(&ctx)?)),
+            Sequencer::new(90.0, Box::new(Fm::new(&ctx)?)),
+        ];
+        let metronome = Metronome::new(&ctx)?;
 
         Ok(Audio {
             ctx,
-            schedule_interval: 0.04,
-            kick_sequencer,
-            snare_sequencer,
-            hat_sequencer,
-            tempo: 90.0,
+            scheduling: Default::default(),
+            sequencers,
+            transport: Transport::new(90.0),
+            metronome,
+            mutator: None,
+            markov_models: HashMap::new(),
+            morphs: HashMap::new(),
+            events: VecDeque::new(),
+            pending_events: Vec::new(),
+            event_callback: None,
         })
     }
 
-    fn get_sequencer(&mut self, seq: &str) -> &mut Sequencer {
-        match seq {
-            "snare" => &mut self.snare_sequencer,
-            "hat" => &mut self.hat_sequencer,
-            &_ => &mut self.kick_sequencer,
-        }
+    fn get_sequencer(&mut self, seq: &str) -> Result<&mut Sequencer, Error> {
+        self.sequencers
+            .iter_mut()
+            .find(|sequencer| sequencer.name() == seq)
+            .ok_or_else(|| Error::UnknownTrack(seq.to_string()))
+    }
+
+    fn update_sound(&mut self, params: SoundParams) -> Result<(), JsValue> {
+        params.validate()?;
+        self.get_sequencer(params.name())?.sound.update(params);
+        Ok(())
+    }
+
+    fn update_volume(&mut self, seq_name: &str, volume: f32) -> Result<(), JsValue> {
+        let ctx = self.ctx.clone();
+        self.get_sequencer(seq_name)?
+            .sound
+            .update_volume(&ctx, volume)
     }
 
     #[wasm_bindgen]
@@ -71,7 +135,7 @@ impl Audio {
         punch: f32,
         volume: f32,
     ) -> Result<(), JsValue> {
-        let wave = wave_string_to_osc(wave_str);
+        let wave = wave_str.parse()?;
         let kick = KickParams {
             freq,
             pitch,
@@ -79,47 +143,170 @@ impl Audio {
             decay,
             punch,
             volume,
+            ..self.kick_params()?
         };
-        self.kick_sequencer.sound.update(SoundParams::Kick(kick));
-        Ok(())
+        self.update_sound(SoundParams::Kick(kick))
+    }
+
+    /// Sets the kick's pitch sweep, amp envelope and click, keeping the rest.
+    /// Leaving `pitch_curve` or `hold` undefined keeps the original
+    /// exponential sweep and a hold that follows the decay.
+    #[wasm_bindgen]
+    pub fn update_kick_envelope(
+        &mut self,
+        pitch_decay: f32,
+        pitch_curve: Option<f32>,
+        attack: f32,
+        hold: Option<f32>,
+        click: f32,
+        click_tone: f32,
+    ) -> Result<(), JsValue> {
+        let kick = KickParams {
+            pitch_decay,
+            pitch_curve,
+            attack,
+            hold,
+            click,
+            click_tone,
+            ..self.kick_params()?
+        };
+        self.update_sound(SoundParams::Kick(kick))
+    }
+
+    /// Switches a pitched track to a custom wave made of these harmonic
+    /// amplitudes, fundamental first.
+    #[wasm_bindgen]
+    pub fn update_wavetable(&mut self, seq_name: &str, harmonics: &[f32]) -> Result<(), JsValue> {
+        let wave = Wave::custom(harmonics)?;
+        let mut params = self.get_sequencer(seq_name)?.sound.params();
+        *params
+            .wave_mut()
+            .ok_or_else(|| Error::NoWave(seq_name.to_string()))? = wave;
+        self.update_sound(params)
+    }
+
+    fn kick_params(&mut self) -> Result<KickParams, Error> {
+        match self.get_sequencer("kick")?.sound.params() {
+            SoundParams::Kick(params) => Ok(params),
+            params => Err(Error::MismatchedSound {
+                track: "kick".to_string(),
+                sound: params.name(),
+            }),
+        }
+    }
+
+    fn snare_params(&mut self) -> Result<SnareParams, Error> {
+        match self.get_sequencer("snare")?.sound.params() {
+            SoundParams::Snare(params) => Ok(params),
+            params => Err(Error::MismatchedSound {
+                track: "snare".to_string(),
+                sound: params.name(),
+            }),
+        }
     }
 
     #[wasm_bindgen]
     pub fn update_kick_volume(&mut self, volume: f32) -> Result<(), JsValue> {
-        self.kick_sequencer.sound.update_volume(&self.ctx, volume)?;
-        Ok(())
+        check_range("kick volume", volume.into(), 0.0, 1.0)?;
+        self.update_volume("kick", volume)
     }
 
     #[wasm_bindgen]
     pub fn update_snare_volume(&mut self, volume: f32) -> Result<(), JsValue> {
-        self.snare_sequencer.sound.update_volume(&self.ctx, volume)?;
-        Ok(())
+        check_range("snare volume", volume.into(), 0.0, 1.0)?;
+        self.update_volume("snare", volume)
     }
 
     #[wasm_bindgen]
     pub fn update_hat_volume(&mut self, volume: f32) -> Result<(), JsValue> {
-        self.hat_sequencer.sound.update_volume(&self.ctx, volume)?;
-        Ok(())
+        check_range("hat volume", volume.into(), 0.0, 1.0)?;
+        self.update_volume("hat", volume)
+    }
+
+    #[wasm_bindgen]
+    pub fn update_clap_volume(&mut self, volume: f32) -> Result<(), JsValue> {
+        check_range("clap volume", volume.into(), 0.0, 1.0)?;
+        self.update_volume("clap", volume)
+    }
+
+    #[wasm_bindgen]
+    pub fn update_tom_volume(&mut self, volume: f32) -> Result<(), JsValue> {
+        check_range("tom volume", volume.into(), 0.0, 1.0)?;
+        self.update_volume("tom", volume)
+    }
+
+    #[wasm_bindgen]
+    pub fn update_cymbal_volume(&mut self, volume: f32) -> Result<(), JsValue> {
+        check_range("cymbal volume", volume.into(), 0.0, 1.0)?;
+        self.update_volume("cymbal", volume)
+    }
+
+    #[wasm_bindgen]
+    pub fn update_cowbell_volume(&mut self, volume: f32) -> Result<(), JsValue> {
+        check_range("cowbell volume", volume.into(), 0.0, 1.0)?;
+        self.update_volume("cowbell", volume)
+    }
+
+    #[wasm_bindgen]
+    pub fn update_rimshot_volume(&mut self, volume: f32) -> Result<(), JsValue> {
+        check_range("rimshot volume", volume.into(), 0.0, 1.0)?;
+        self.update_volume("rimshot", volume)
+    }
+
+    #[wasm_bindgen]
+    pub fn update_sampler_volume(&mut self, volume: f32) -> Result<(), JsValue> {
+        check_range("sampler volume", volume.into(), 0.0, 1.0)?;
+        self.update_volume("sampler", volume)
+    }
+
+    #[wasm_bindgen]
+    pub fn update_fm_volume(&mut self, volume: f32) -> Result<(), JsValue> {
+        check_range("fm volume", volume.into(), 0.0, 1.0)?;
+        self.update_volume("fm", volume)
     }
 
     #[wasm_bindgen]
     pub fn update_snare(
         &mut self,
         freq: f32,
-        blend: f32,
+        tone: f32,
+        snappy: f32,
         decay: f32,
         punch: f32,
         volume: f32,
     ) -> Result<(), JsValue> {
         let snare = SnareParams {
             freq,
-            blend,
+            tone,
+            snappy,
             decay,
             punch,
             volume,
+            ..self.snare_params()?
         };
-        self.snare_sequencer.sound.update(SoundParams::Snare(snare));
-        Ok(())
+        self.update_sound(SoundParams::Snare(snare))
+    }
+
+    /// Sets the snare's body tuning and pitch envelope and the snappy
+    /// filter and decay, keeping the rest.
+    #[wasm_bindgen]
+    pub fn update_snare_shape(
+        &mut self,
+        ratio: f32,
+        pitch: f32,
+        pitch_time: f32,
+        snappy_tone: f32,
+        snappy_decay: f32,
+    ) -> Result<(), JsValue> {
+        let snare = SnareParams {
+            ratio,
+            pitch,
+            pitch_time,
+            snappy_tone,
+            snappy_decay,
+            ..self.snare_params()?
+        };
+        self.update_sound(SoundParams::Snare(snare))
     }
 
     #[wasm_bindgen]
@@ -136,183 +323,675 @@ impl Audio {
             punch,
             volume,
         };
-        self.hat_sequencer.sound.update(SoundParams::Hat(hat));
-        Ok(())
+        self.update_sound(SoundParams::Hat(hat))
+    }
+
+    #[wasm_bindgen]
+    pub fn update_clap(
+        &mut self,
+        tone: f32,
+        spread: f32,
+        decay: f32,
+        punch: f32,
+        volume: f32,
+    ) -> Result<(), JsValue> {
+        let clap = ClapParams {
+            tone,
+            spread,
+            decay,
+            punch,
+            volume,
+        };
+        self.update_sound(SoundParams::Clap(clap))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    #[wasm_bindgen]
+    pub fn update_tom(
+        &mut self,
+        note: f32,
+        tune: f32,
+        wave_str: &str,
+        pitch_amount: f32,
+        pitch_time: f32,
+        decay: f32,
+        noise: f32,
+        volume: f32,
+    ) -> Result<(), JsValue> {
+        let tom = TomParams {
+            note,
+            tune,
+            wave: wave_str.parse()?,
+            pitch_amount,
+            pitch_time,
+            decay,
+            noise,
+            volume,
+        };
+        self.update_sound(SoundParams::Tom(tom))
+    }
+
+    #[wasm_bindgen]
+    pub fn update_cymbal(
+        &mut self,
+        tone: f32,
+        closed_decay: f32,
+        open_decay: f32,
+        open: bool,
+        volume: f32,
+    ) -> Result<(), JsValue> {
+        let cymbal = CymbalParams {
+            tone,
+            closed_decay,
+            open_decay,
+            open,
+            volume,
+        };
+        self.update_sound(SoundParams::Cymbal(cymbal))
+    }
+
+    #[wasm_bindgen]
+    pub fn update_cowbell(
+        &mut self,
+        freq: f32,
+        tone: f32,
+        decay: f32,
+        volume: f32,
+    ) -> Result<(), JsValue> {
+        let cowbell = CowbellParams {
+            freq,
+            tone,
+            decay,
+            volume,
+        };
+        self.update_sound(SoundParams::Cowbell(cowbell))
+    }
+
+    #[wasm_bindgen]
+    pub fn update_rimshot(
+        &mut self,
+        tone: f32,
+        resonance: f32,
+        noise: f32,
+        decay: f32,
+        volume: f32,
+    ) -> Result<(), JsValue> {
+        let rimshot = RimshotParams {
+            tone,
+            resonance,
+            noise,
+            decay,
+            volume,
+        };
+        self.update_sound(SoundParams::Rimshot(rimshot))
+    }
+
+    #[wasm_bindgen]
+    pub fn update_sampler(
+        &mut self,
+        start: f32,
+        rate: f32,
+        reverse: bool,
+        decay: f32,
+        volume: f32,
+    ) -> Result<(), JsValue> {
+        let sampler = SamplerParams {
+            start,
+            rate,
+            reverse,
+            decay,
+            volume,
+        };
+        self.update_sound(SoundParams::Sampler(sampler))
+    }
+
+    #[wasm_bindgen]
+    pub fn update_fm(
+        &mut self,
+        freq: f32,
+        ratio: f32,
+        index: f32,
+        index_decay: f32,
+        decay: f32,
+        volume: f32,
+    ) -> Result<(), JsValue> {
+        let fm = FmParams {
+            freq,
+            ratio,
+            index,
+            index_decay,
+            decay,
+            volume,
+        };
+        self.update_sound(SoundParams::Fm(fm))
+    }
+
+    /// Decodes the bytes of a WAV file and loads them into the sampler.
+    #[wasm_bindgen]
+    pub fn load_sample(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        let wav = sounds::wav::decode(bytes)?;
+        let ctx = self.ctx.clone();
+        self.get_sequencer("sampler")?.sound.load_sample(&ctx, &wav)
     }
 
     #[wasm_bindgen]
     pub fn start(&mut self) -> Result<(), JsValue> {
-        self.kick_sequencer.play(&self.ctx);
-        self.snare_sequencer.play(&self.ctx);
-        self.hat_sequencer.play(&self.ctx);
+        self.start_at(0, 0)
+    }
+
+    /// Starts from sixteenth `step` of `bar`, counting bars from zero.
+    #[wasm_bindgen]
+    pub fn start_at(&mut self, bar: u32, step: u32) -> Result<(), JsValue> {
+        self.start_from(bar, step, 0)
+    }
+
+    /// Clicks for one or two bars before the tracks come in. There is no
+    /// recording to lead into yet, so this only counts in playback.
+    #[wasm_bindgen]
+    pub fn start_with_count_in(&mut self, bars: u32) -> Result<(), JsValue> {
+        check_range("count-in bars", bars.into(), 1.0, 2.0)?;
+        self.start_from(0, 0, bars)
+    }
+
+    fn start_from(&mut self, bar: u32, step: u32, count_in: u32) -> Result<(), JsValue> {
+        let beats_per_bar = self.transport.beats_per_bar;
+        let steps_per_bar = STEPS_PER_BEAT * beats_per_bar as u32;
+        check_range("start step", step.into(), 0.0, (steps_per_bar - 1).into())?;
+        let now = self.ctx.current_time();
+        let into_bar = step as f64 / STEPS_PER_BEAT as f64;
+        for seq in self.sequencers.iter_mut() {
+            seq.play(bar, beats_per_bar, into_bar);
+        }
+        let bar_start = (bar as f64 - count_in as f64) * beats_per_bar as f64;
+        let beats = bar_start + into_bar;
+        self.transport.start(now + self.lead(), beats);
+        self.metronome.reset(&self.transport, now);
+        if let Some(mutator) = &mut self.mutator {
+            mutator.reset_clock();
+        }
         Ok(())
     }
 
+    /// How late the transport starts so that no track's first hit starts
+    /// sooner than `margin` from now.
+    fn lead(&self) -> f64 {
+        self.sequencers
+            .iter()
+            .map(Sequencer::lead)
+            .fold(0.0, f64::max)
+    }
+
+    /// Stops scheduling but keeps every track's position for `resume`.
     #[wasm_bindgen]
-    pub fn stop(&mut self) {
-        self.kick_sequencer.stop();
-        self.snare_sequencer.stop();
-        self.hat_sequencer.stop();
+    pub fn pause(&mut self) -> Result<(), JsValue> {
+        if !self.transport.is_running() {
+            return Ok(());
+        }
+        let time = self.ctx.current_time();
+        let margin = self.scheduling.margin;
+        for seq in self.sequencers.iter_mut() {
+            seq.pause(time, &self.transport, margin)?;
+        }
+        // Freeze at the position being heard, so that hits after `resume`
+        // start `margin` after their step again.
+        self.transport.pause(time - margin);
+        self.events.retain(|event| event.time < time);
+        self.pending_events.retain(|event| event.time < time);
+        Ok(())
     }
 
     #[wasm_bindgen]
-    pub fn schedule(&mut self) -> Result<(), JsValue> {
-        self.kick_sequencer
-            .schedule_sounds(&self.ctx, self.schedule_interval)?;
-        self.snare_sequencer
-            .schedule_sounds(&self.ctx, self.schedule_interval)?;
-        self.hat_sequencer
-            .schedule_sounds(&self.ctx, self.schedule_interval)?;
+    pub fn resume(&mut self) {
+        if self.transport.is_running() {
+            return;
+        }
+        let time = self.ctx.current_time();
+        // Humanized hits can start before their step.
+        let origin = time
+            + self
+                .sequencers
+                .iter()
+                .map(Sequencer::earliest)
+                .fold(0.0, f64::max);
+        for seq in self.sequencers.iter_mut() {
+            seq.resume(origin);
+        }
+        self.transport.resume(origin);
+        self.metronome.reset(&self.transport, time);
+    }
+
+    #[wasm_bindgen]
+    pub fn enable_metronome(&mut self, enabled: bool) {
+        self.metronome.enabled = enabled;
+    }
+
+    #[wasm_bindgen]
+    pub fn update_metronome_volume(&mut self, volume: f32) -> Result<(), JsValue> {
+        self.metronome.set_volume(&self.ctx, volume)
+    }
+
+    /// Loops every track over steps `first` to `last`, inclusive.
+    #[wasm_bindgen]
+    pub fn set_loop_region(&mut self, first: i8, last: i8) -> Result<(), JsValue> {
+        check_range("loop region start", first.into(), 0.0, 15.0)?;
+        check_range("loop region end", last.into(), first.into(), 15.0)?;
+        for seq in self.sequencers.iter_mut() {
+            seq.loop_region = Some((first, last));
+        }
         Ok(())
     }
 
     #[wasm_bindgen]
-    pub fn get_steps(&mut self, seq_name: &str) -> i8 {
+    pub fn clear_loop_region(&mut self) {
+        for seq in self.sequencers.iter_mut() {
+            seq.loop_region = None;
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn stop(&mut self) -> Result<(), JsValue> {
         let time = self.ctx.current_time();
-        let seq = self.get_sequencer(seq_name);
-        get_sequencer_steps(seq, time)
+        for seq in self.sequencers.iter_mut() {
+            seq.silence(time)?;
+            seq.stop();
+        }
+        self.transport.stop();
+        self.events.clear();
+        self.pending_events.clear();
+        Ok(())
     }
 
     #[wasm_bindgen]
-    pub fn update_sequencer_length(&mut self, seq_name: &str, length: i8) {
-        let tempo = self.tempo;
-        let seq = self.get_sequencer(seq_name);
-        seq.steps = length;
-        seq.step_delta = (60.0 / tempo as f64) * (4.0 / length as f64);
+    pub fn update_mute(&mut self, seq_name: &str, muted: bool) -> Result<(), JsValue> {
+        let time = self.ctx.current_time();
+        self.get_sequencer(seq_name)?.set_muted(muted, time)
     }
 
     #[wasm_bindgen]
-    pub fn update_offset(&mut self, seq_name: &str, offset: f64) {
-        let seq = self.get_sequencer(seq_name);
-        seq.offset = offset;
+    pub fn schedule(&mut self) -> Result<(), JsValue> {
+        if !self.transport.is_running() {
+            return Ok(());
+        }
+        let now = self.ctx.current_time();
+        let mut hits = Vec::new();
+        for (track, seq) in self.sequencers.iter_mut().enumerate() {
+            let times = seq.schedule_sounds(&self.ctx, &self.transport, &self.scheduling)?;
+            if let Some(group) = seq.choke_group {
+                hits.extend(times.into_iter().map(|time| (track, group, time)));
+            }
+            self.pending_events.extend(seq.take_events());
+        }
+        for (hit_track, group, time) in hits {
+            for (track, seq) in self.sequencers.iter_mut().enumerate() {
+                if track != hit_track && seq.choke_group == Some(group) {
+                    seq.choke(time)?;
+                }
+            }
+        }
+        let until = self.ctx.current_time() + self.scheduling.lookahead;
+        self.metronome
+            .schedule(&self.ctx, &self.transport, until, self.scheduling.margin)?;
+        self.mutate_patterns();
+        // Every track has scheduled all the steps heard before this.
+        let scheduling = &self.scheduling;
+        let heard_until =
+            now + scheduling.lookahead + scheduling.margin + scheduling.output_latency;
+        let events = release_events(&mut self.pending_events, heard_until);
+        self.emit_events(events)
+    }
+
+    fn emit_events(&mut self, events: Vec<StepEvent>) -> Result<(), JsValue> {
+        if let Some(callback) = &self.event_callback {
+            for event in events.iter() {
+                callback.call1(&JsValue::NULL, &serde_wasm_bindgen::to_value(event)?)?;
+            }
+        }
+        self.events.extend(events);
+        while self.events.len() > EVENT_QUEUE_LIMIT {
+            self.events.pop_front();
+        }
+        Ok(())
+    }
+
+    /// Returns the step events scheduled since the last call, ordered by time.
+    #[wasm_bindgen]
+    pub fn drain_events(&mut self) -> Result<JsValue, JsValue> {
+        let events: Vec<StepEvent> = self.events.drain(..).collect();
+        Ok(serde_wasm_bindgen::to_value(&events)?)
     }
 
+    /// Calls `callback` with every step event, in time order, once no earlier
+    /// event can still be scheduled.
     #[wasm_bindgen]
-    pub fn update_tempo(&mut self, tempo: f32) {
-        self.tempo = tempo;
-        self.kick_sequencer.step_delta =
-            (60.0 / tempo as f64) * (4.0 / self.kick_sequencer.steps as f64);
-        self.snare_sequencer.step_delta =
-            (60.0 / tempo as f64) * (4.0 / self.snare_sequencer.steps as f64);
-        self.hat_sequencer.step_delta =
-            (60.0 / tempo as f64) * (4.0 / self.hat_sequencer.steps as f64);
-    }
-
-    #[wasm_bindgen]
-    pub fn update_kick_steps(&mut self, steps: JsValue) {
-        let elements: Vec<KickValues> = steps.into_serde().unwrap();
-        let mut steps: [Option<Trigger<SoundParams>>; 16] = Default::default();
-        for i in 0..16 {
-            steps[i] = match &elements[i] {
-                KickValues {
-                    freq,
-                    pitch,
-                    wave,
-                    decay,
-                    punch,
-                    volume,
-                    step_type,
-                } => match step_type.as_str() {
-                    "trigger" => {
-                        self.kick_sequencer
-                            .sound
-                            .update(SoundParams::Kick(KickParams {
-                                freq: *freq,
-                                pitch: *pitch,
-                                wave: wave_string_to_osc(wave),
-                                decay: *decay,
-                                punch: *punch,
-                                volume: *volume,
-                            }));
-                        Some(Trigger::NormalTrigger)
-                    }
-                    "lock_trigger" => Some(Trigger::LockTrigger(SoundParams::Kick(KickParams {
-                        freq: *freq,
-                        pitch: *pitch,
-                        wave: wave_string_to_osc(wave),
-                        decay: *decay,
-                        punch: *punch,
-                        volume: *volume,
-                    }))),
-                    &_ => None,
-                },
+    pub fn set_event_callback(&mut self, callback: Option<js_sys::Function>) {
+        self.event_callback = callback;
+    }
+
+    /// Sets how far ahead, in seconds, each call to `schedule` looks. It has
+    /// to cover the time between calls, including when the page is throttled.
+    #[wasm_bindgen]
+    pub fn update_lookahead(&mut self, seconds: f32) -> Result<(), JsValue> {
+        check_range("lookahead", seconds.into(), 0.01, 2.0)?;
+        self.scheduling.lookahead = seconds.into();
+        Ok(())
+    }
+
+    /// Sets how long after its step every hit starts, in seconds, 0.05 by
+    /// default. It has to cover the time from `start` or `resume` to the
+    /// first call to `schedule`, so it can be lower where that call follows
+    /// at once. The playhead allows for it.
+    #[wasm_bindgen]
+    pub fn update_margin(&mut self, seconds: f64) -> Result<(), JsValue> {
+        check_range("margin", seconds, 0.0, 1.0)?;
+        self.scheduling.margin = seconds;
+        Ok(())
+    }
+
+    /// Sets the output latency in seconds, the time between a hit starting
+    /// and it reaching the speaker. The playhead and `position` lag by this
+    /// much so that they match what is heard; hits are not moved.
+    #[wasm_bindgen]
+    pub fn update_latency(&mut self, seconds: f64) -> Result<(), JsValue> {
+        check_range("latency", seconds, 0.0, 1.0)?;
+        self.scheduling.output_latency = seconds;
+        Ok(())
+    }
+
+    /// Sets the output latency from the context's `baseLatency` and
+    /// `outputLatency`, where the browser reports them, and returns it.
+    #[wasm_bindgen]
+    pub fn auto_latency(&mut self) -> Result<f64, JsValue> {
+        let mut latency = 0.0;
+        for name in ["baseLatency", "outputLatency"].iter() {
+            let value = js_sys::Reflect::get(&self.ctx, &JsValue::from_str(name))?;
+            latency += value.as_f64().unwrap_or(0.0);
+        }
+        self.scheduling.output_latency = latency.clamp(0.0, 1.0);
+        Ok(self.scheduling.output_latency)
+    }
+
+    /// Either "skip" hits that are already overdue when scheduled, the
+    /// default, or play them "late".
+    #[wasm_bindgen]
+    pub fn update_catch_up(&mut self, policy: &str) -> Result<(), JsValue> {
+        self.scheduling.catch_up = policy.parse::<CatchUp>()?;
+        Ok(())
+    }
+
+    /// Returns how many hits were played late or skipped since the last call.
+    #[wasm_bindgen]
+    pub fn take_schedule_stats(&mut self) -> Result<JsValue, JsValue> {
+        let mut stats = ScheduleStats::default();
+        for seq in self.sequencers.iter_mut() {
+            let track = std::mem::take(&mut seq.stats);
+            stats.late += track.late;
+            stats.missed += track.missed;
+        }
+        Ok(serde_wasm_bindgen::to_value(&stats)?)
+    }
+
+    #[wasm_bindgen]
+    pub fn current_time(&self) -> f64 {
+        self.ctx.current_time()
+    }
+
+    fn mutate_patterns(&mut self) {
+        let bar = self.transport.bar_at(self.ctx.current_time());
+        let mutator = match &mut self.mutator {
+            Some(mutator) => mutator,
+            None => return,
+        };
+        if !mutator.is_due(bar) {
+            return;
+        }
+        let tracks: Vec<(Sequence, i8)> = self
+            .sequencers
+            .iter()
+            .map(|seq| (seq.sequence, seq.steps))
+            .collect();
+        let mutated = mutator.mutate(&tracks);
+        // Morphing tracks get their next bar from the morph instead.
+        for (seq, sequence) in self.sequencers.iter_mut().zip(mutated) {
+            if !self.morphs.contains_key(seq.name()) {
+                seq.next_sequence = Some(sequence);
             }
         }
-        self.kick_sequencer.sequence = steps;
-    }
-
-    #[wasm_bindgen]
-    pub fn update_snare_steps(&mut self, steps: JsValue) {
-        let elements: Vec<SnareValues> = steps.into_serde().unwrap();
-        let mut steps: [Option<Trigger<SoundParams>>; 16] = Default::default();
-        for i in 0..16 {
-            steps[i] = match &elements[i] {
-                SnareValues {
-                    freq,
-                    blend,
-                    decay,
-                    punch,
-                    volume,
-                    step_type,
-                } => match step_type.as_str() {
-                    "trigger" => {
-                        self.snare_sequencer
-                            .sound
-                            .update(SoundParams::Snare(SnareParams {
-                                freq: *freq,
-                                blend: *blend,
-                                decay: *decay,
-                                punch: *punch,
-                                volume: *volume,
-                            }));
-                        Some(Trigger::NormalTrigger)
-                    }
-                    "lock_trigger" => Some(Trigger::LockTrigger(SoundParams::Snare(SnareParams {
-                        freq: *freq,
-                        blend: *blend,
-                        decay: *decay,
-                        punch: *punch,
-                        volume: *volume,
-                    }))),
-                    &_ => None,
-                },
+    }
+
+    #[wasm_bindgen]
+    pub fn enable_mutation(
+        &mut self,
+        seed: u32,
+        flip_probability: f32,
+        param_amount: f32,
+        rotate_probability: f32,
+        every_bars: u32,
+    ) -> Result<(), JsValue> {
+        let settings = MutationSettings {
+            flip_probability,
+            param_amount,
+            rotate_probability,
+            every_bars,
+        };
+        settings.validate()?;
+        self.mutator = Some(Mutator::new(seed.into(), settings));
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn disable_mutation(&mut self) {
+        self.mutator = None;
+        for seq in self.sequencers.iter_mut() {
+            if !self.morphs.contains_key(seq.name()) {
+                seq.next_sequence = None;
             }
         }
-        self.snare_sequencer.sequence = steps;
-    }
-
-    #[wasm_bindgen]
-    pub fn update_hat_steps(&mut self, steps: JsValue) {
-        let elements: Vec<HatValues> = steps.into_serde().unwrap();
-        let mut steps: [Option<Trigger<SoundParams>>; 16] = Default::default();
-        for i in 0..16 {
-            steps[i] = match &elements[i] {
-                HatValues {
-                    freq,
-                    decay,
-                    punch,
-                    volume,
-                    step_type,
-                } => match step_type.as_str() {
-                    "trigger" => {
-                        self.hat_sequencer.sound.update(SoundParams::Hat(HatParams {
-                            freq: *freq,
-                            decay: *decay,
-                            punch: *punch,
-                            volume: *volume,
-                        }));
-                        Some(Trigger::NormalTrigger)
-                    }
-                    "lock_trigger" => Some(Trigger::LockTrigger(SoundParams::Hat(HatParams {
-                        freq: *freq,
-                        decay: *decay,
-                        punch: *punch,
-                        volume: *volume,
-                    }))),
-                    &_ => None,
-                },
+    }
+
+    #[wasm_bindgen]
+    pub fn undo_mutation(&mut self) -> Result<bool, JsValue> {
+        let previous = match self.mutator.as_mut().and_then(|mutator| mutator.undo()) {
+            Some(previous) => previous,
+            None => return Ok(false),
+        };
+        let time = self.ctx.current_time();
+        for (seq, sequence) in self.sequencers.iter_mut().zip(previous) {
+            if !self.morphs.contains_key(seq.name()) {
+                seq.set_sequence(sequence, time)?;
             }
         }
-        self.hat_sequencer.sequence = steps;
+        Ok(true)
+    }
+
+    #[wasm_bindgen]
+    pub fn get_steps(&mut self, seq_name: &str) -> Result<i8, JsValue> {
+        let time = self.ctx.current_time();
+        let seq = self.get_sequencer(seq_name)?;
+        Ok(get_sequencer_steps(seq, time))
     }
+
+    #[wasm_bindgen]
+    pub fn update_sequencer_length(&mut self, seq_name: &str, length: i8) -> Result<(), JsValue> {
+        check_range("sequencer length", length.into(), 1.0, 16.0)?;
+        let bar_duration = self.transport.bar_duration();
+        let beats_per_bar = self.transport.beats_per_bar;
+        let seq = self.get_sequencer(seq_name)?;
+        seq.steps = length;
+        seq.set_timing(bar_duration, beats_per_bar);
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn update_offset(
+        &mut self,
+        seq_name: &str,
+        amount: f64,
+        unit: &str,
+    ) -> Result<(), JsValue> {
+        let offset = Offset::new(amount, unit)?;
+        let seq = self.get_sequencer(seq_name)?;
+        seq.offset = offset;
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn update_humanize(
+        &mut self,
+        seq_name: &str,
+        timing: f64,
+        velocity: f32,
+        seed: u32,
+    ) -> Result<(), JsValue> {
+        let humanize = Humanize::new(timing, velocity, seed.into())?;
+        let seq = self.get_sequencer(seq_name)?;
+        seq.humanize = if timing == 0.0 && velocity == 0.0 {
+            None
+        } else {
+            Some(humanize)
+        };
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn update_choke_group(&mut self, seq_name: &str, group: Option<u8>) -> Result<(), JsValue> {
+        self.get_sequencer(seq_name)?.choke_group = group;
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn update_tempo(&mut self, tempo: f32) -> Result<(), JsValue> {
+        check_range("tempo", tempo.into(), 20.0, 400.0)?;
+        self.transport.set_tempo(tempo, self.ctx.current_time());
+        self.update_timing();
+        Ok(())
+    }
+
+    /// Sets how many beats make up a bar, and so one loop of every track.
+    #[wasm_bindgen]
+    pub fn update_time_signature(&mut self, beats_per_bar: u8) -> Result<(), JsValue> {
+        self.transport.set_beats_per_bar(beats_per_bar)?;
+        self.update_timing();
+        Ok(())
+    }
+
+    fn update_timing(&mut self) {
+        let bar_duration = self.transport.bar_duration();
+        let beats_per_bar = self.transport.beats_per_bar;
+        for seq in self.sequencers.iter_mut() {
+            seq.set_timing(bar_duration, beats_per_bar);
+        }
+    }
+
+    /// Returns the bar, beat and step being heard now, allowing for latency,
+    /// with each track's loop count.
+    #[wasm_bindgen]
+    pub fn position(&self) -> Result<JsValue, JsValue> {
+        let time =
+            self.ctx.current_time() - self.scheduling.margin - self.scheduling.output_latency;
+        let beats = self.transport.beats_at(time);
+        let loops = self
+            .sequencers
+            .iter()
+            .map(|seq| (seq.name(), seq.loops_at(beats)))
+            .collect();
+        let position = self.transport.position(time, loops);
+        Ok(serde_wasm_bindgen::to_value(&position)?)
+    }
+
+    #[wasm_bindgen]
+    pub fn update_steps(&mut self, seq_name: &str, pattern: JsValue) -> Result<(), JsValue> {
+        let pattern: Pattern = serde_wasm_bindgen::from_value(pattern)
+            .map_err(|err| Error::MalformedSteps(err.to_string()))?;
+        let sequence = pattern.to_sequence(seq_name)?;
+        let time = self.ctx.current_time();
+        self.get_sequencer(seq_name)?.set_sequence(sequence, time)
+    }
+
+    #[wasm_bindgen]
+    pub fn load_pattern(&mut self, seq_name: &str, json: &str) -> Result<(), JsValue> {
+        let sequence = Pattern::from_json(json)?.to_sequence(seq_name)?;
+        let time = self.ctx.current_time();
+        self.get_sequencer(seq_name)?.set_sequence(sequence, time)
+    }
+
+    #[wasm_bindgen]
+    pub fn train_markov(&mut self, seq_name: &str, corpus: JsValue) -> Result<(), JsValue> {
+        self.get_sequencer(seq_name)?;
+        let corpus: Vec<Pattern> = serde_wasm_bindgen::from_value(corpus)
+            .map_err(|err| Error::MalformedSteps(err.to_string()))?;
+        let model = MarkovModel::train(seq_name, &corpus)?;
+        self.markov_models.insert(seq_name.to_string(), model);
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn generate_markov(
+        &mut self,
+        seq_name: &str,
+        temperature: f32,
+        seed: u32,
+    ) -> Result<JsValue, JsValue> {
+        let model = self
+            .markov_models
+            .get(seq_name)
+            .ok_or_else(|| Error::UntrainedTrack(seq_name.to_string()))?;
+        let mut rng = StdRng::seed_from_u64(seed.into());
+        let sequence = model.generate(temperature, &mut rng)?;
+        let time = self.ctx.current_time();
+        self.get_sequencer(seq_name)?.set_sequence(sequence, time)?;
+        let pattern = Pattern::from_sequence(&sequence);
+        Ok(serde_wasm_bindgen::to_value(&pattern)?)
+    }
+
+    #[wasm_bindgen]
+    pub fn set_morph(
+        &mut self,
+        seq_name: &str,
+        pattern_a: JsValue,
+        pattern_b: JsValue,
+        seed: u32,
+    ) -> Result<(), JsValue> {
+        self.get_sequencer(seq_name)?;
+        let mut sequences = Vec::new();
+        for pattern in [pattern_a, pattern_b] {
+            let pattern: Pattern = serde_wasm_bindgen::from_value(pattern)
+                .map_err(|err| Error::MalformedSteps(err.to_string()))?;
+            sequences.push(pattern.to_sequence(seq_name)?);
+        }
+        let morph = Morph::new(sequences[0], sequences[1], seed.into());
+        self.morphs.insert(seq_name.to_string(), morph);
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_morph(&mut self, seq_name: &str) -> Result<(), JsValue> {
+        self.get_sequencer(seq_name)?.next_sequence = None;
+        self.morphs.remove(seq_name);
+        Ok(())
+    }
+
+    /// Queues every morphing track at `amount`, taking effect on its next bar.
+    #[wasm_bindgen]
+    pub fn update_morph(&mut self, amount: f32) -> Result<(), JsValue> {
+        check_range("morph amount", amount.into(), 0.0, 1.0)?;
+        let seq_names: Vec<String> = self.morphs.keys().cloned().collect();
+        for seq_name in seq_names {
+            let base = self.get_sequencer(&seq_name)?.sound.params();
+            let sequence = self.morphs[&seq_name].at(amount, base)?;
+            self.get_sequencer(&seq_name)?.next_sequence = Some(sequence);
+        }
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn get_pattern(&mut self, seq_name: &str) -> Result<JsValue, JsValue> {
+        let seq = self.get_sequencer(seq_name)?;
+        let pattern = Pattern::from_sequence(&seq.sequence);
+        Ok(serde_wasm_bindgen::to_value(&pattern)?)
+    }
+}
+
+#[wasm_bindgen]
+pub fn pattern_schema() -> String {
+    PATTERN_SCHEMA.to_string()
 }
@@ -0,0 +1,176 @@
+use crate::error::{check_range, Error};
+use crate::sounds::SoundParams;
+use crate::step::{Pattern, Sequence, Step, PATTERN_STEPS};
+use rand::Rng;
+
+const START: usize = 3;
+
+/// Step kinds the chain moves between; locked parameters are drawn from the
+/// ones seen while training.
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Kind {
+    Off,
+    Trigger,
+    Lock,
+}
+
+const KINDS: [Kind; 3] = [Kind::Off, Kind::Trigger, Kind::Lock];
+
+impl Kind {
+    fn of(step: &Step) -> Kind {
+        match step {
+            Step::Off => Kind::Off,
+            Step::Trigger => Kind::Trigger,
+            Step::Lock { .. } => Kind::Lock,
+        }
+    }
+}
+
+/// First-order chain over step kinds, conditioned on the position in the bar
+/// and falling back to position-independent counts for unseen contexts.
+pub struct MarkovModel {
+    positional: [[[u32; 3]; 4]; PATTERN_STEPS],
+    fallback: [[u32; 3]; 4],
+    locks: Vec<(usize, SoundParams)>,
+}
+
+impl MarkovModel {
+    pub fn train(track: &str, corpus: &[Pattern]) -> Result<MarkovModel, Error> {
+        let mut model = MarkovModel {
+            positional: [[[0; 3]; 4]; PATTERN_STEPS],
+            fallback: [[0; 3]; 4],
+            locks: Vec::new(),
+        };
+        for pattern in corpus {
+            model.add(&pattern.to_sequence(track)?);
+        }
+        Ok(model)
+    }
+
+    pub fn add(&mut self, sequence: &Sequence) {
+        let mut previous = START;
+        for (position, step) in sequence.iter().enumerate() {
+            let kind = Kind::of(step) as usize;
+            self.positional[position][previous][kind] += 1;
+            self.fallback[previous][kind] += 1;
+            if let Step::Lock { params } = step {
+                self.locks.push((position, *params));
+            }
+            previous = kind;
+        }
+    }
+
+    /// Generates a new sequence. A temperature of 0 always picks the most
+    /// likely step, 1 follows the corpus and higher values flatten the odds.
+    pub fn generate<R: Rng + ?Sized>(
+        &self,
+        temperature: f32,
+        rng: &mut R,
+    ) -> Result<Sequence, Error> {
+        check_range("temperature", temperature.into(), 0.0, 10.0)?;
+        let mut sequence: Sequence = Default::default();
+        let mut previous = START;
+        for (position, step) in sequence.iter_mut().enumerate() {
+            let counts = match self.positional[position][previous] {
+                [0, 0, 0] => self.fallback[previous],
+                counts => counts,
+            };
+            let kind = pick(&counts, temperature, rng);
+            *step = match kind {
+                Kind::Off => Step::Off,
+                Kind::Trigger => Step::Trigger,
+                Kind::Lock => match self.pick_lock(position, rng) {
+                    Some(params) => Step::Lock { params },
+                    None => Step::Trigger,
+                },
+            };
+            previous = kind as usize;
+        }
+        Ok(sequence)
+    }
+
+    fn pick_lock<R: Rng + ?Sized>(&self, position: usize, rng: &mut R) -> Option<SoundParams> {
+        let at_position: Vec<&SoundParams> = self
+            .locks
+            .iter()
+            .filter(|(lock_position, _)| *lock_position == position)
+            .map(|(_, params)| params)
+            .collect();
+        if !at_position.is_empty() {
+            return Some(*at_position[rng.gen_range(0..at_position.len())]);
+        }
+        if self.locks.is_empty() {
+            return None;
+        }
+        Some(self.locks[rng.gen_range(0..self.locks.len())].1)
+    }
+}
+
+fn pick<R: Rng + ?Sized>(counts: &[u32; 3], temperature: f32, rng: &mut R) -> Kind {
+    if counts.iter().all(|count| *count == 0) {
+        return Kind::Off;
+    }
+    if temperature == 0.0 {
+        let most_likely = (0..3).max_by_key(|kind| counts[*kind]).unwrap_or(0);
+        return KINDS[most_likely];
+    }
+    let weights: Vec<f64> = counts
+        .iter()
+        .map(|count| (*count as f64).powf(1.0 / temperature as f64))
+        .collect();
+    let mut target = rng.gen::<f64>() * weights.iter().sum::<f64>();
+    for (kind, weight) in KINDS.iter().zip(&weights) {
+        if target < *weight {
+            return *kind;
+        }
+        target -= weight;
+    }
+    KINDS[weights
+        .iter()
+        .rposition(|weight| *weight > 0.0)
+        .unwrap_or(0)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sounds::kick::KickParams;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn four_on_the_floor() -> Pattern {
+        let mut sequence: Sequence = Default::default();
+        for step in (0..PATTERN_STEPS).step_by(4) {
+            sequence[step] = Step::Trigger;
+        }
+        sequence[14] = Step::Lock {
+            params: SoundParams::Kick(KickParams {
+                freq: 60.0,
+                ..KickParams::default()
+            }),
+        };
+        Pattern::from_sequence(&sequence)
+    }
+
+    #[test]
+    fn zero_temperature_reproduces_a_single_pattern() {
+        let pattern = four_on_the_floor();
+        let model = MarkovModel::train("kick", std::slice::from_ref(&pattern)).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let generated = model.generate(0.0, &mut rng).unwrap();
+        assert_eq!(Pattern::from_sequence(&generated), pattern);
+    }
+
+    #[test]
+    fn generation_is_seedable() {
+        let model = MarkovModel::train("kick", &[four_on_the_floor()]).unwrap();
+        let first = model.generate(2.0, &mut StdRng::seed_from_u64(9)).unwrap();
+        let second = model.generate(2.0, &mut StdRng::seed_from_u64(9)).unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn rejects_patterns_for_other_tracks() {
+        assert!(MarkovModel::train("snare", &[four_on_the_floor()]).is_err());
+    }
+}
@@ -0,0 +1,84 @@
+use crate::error::check_range;
+use crate::transport::Transport;
+use wasm_bindgen::prelude::*;
+use web_sys::{AudioContext, GainNode};
+
+const ACCENT_FREQ: f32 = 1600.0;
+const BEAT_FREQ: f32 = 1000.0;
+const CLICK_LENGTH: f64 = 0.05;
+
+/// Click on every beat of the transport, louder and higher on the downbeat.
+/// It plays through its own output rather than a track, so a render or
+/// recording tapped from the track outputs would leave it out. Neither
+/// exists yet.
+pub struct Metronome {
+    pub enabled: bool,
+    output: GainNode,
+    next_beat: i64,
+}
+
+impl Metronome {
+    pub fn new(ctx: &AudioContext) -> Result<Self, JsValue> {
+        let output = ctx.create_gain()?;
+        output.gain().set_value(0.5);
+        output.connect_with_audio_node(&ctx.destination())?;
+        Ok(Metronome {
+            enabled: false,
+            output,
+            next_beat: 0,
+        })
+    }
+
+    pub fn set_volume(&self, ctx: &AudioContext, volume: f32) -> Result<(), JsValue> {
+        check_range("metronome volume", volume.into(), 0.0, 1.0)?;
+        self.output
+            .gain()
+            .linear_ramp_to_value_at_time(volume, ctx.current_time() + 0.01)?;
+        Ok(())
+    }
+
+    /// Picks up from the first beat at or after `time`.
+    pub fn reset(&mut self, transport: &Transport, time: f64) {
+        self.next_beat = transport.beats_at(time).ceil() as i64;
+    }
+
+    /// Schedules the clicks that fall before `until`, `margin` seconds late
+    /// like every other hit. Beats before zero belong to a count-in and always
+    /// click.
+    pub fn schedule(
+        &mut self,
+        ctx: &AudioContext,
+        transport: &Transport,
+        until: f64,
+        margin: f64,
+    ) -> Result<(), JsValue> {
+        while let Some(time) = transport.time_at(self.next_beat as f64) {
+            if time >= until {
+                break;
+            }
+            if self.enabled || self.next_beat < 0 {
+                let accent = self.next_beat.rem_euclid(transport.beats_per_bar as i64) == 0;
+                self.click(ctx, time + margin, accent)?;
+            }
+            self.next_beat += 1;
+        }
+        Ok(())
+    }
+
+    fn click(&self, ctx: &AudioContext, time: f64, accent: bool) -> Result<(), JsValue> {
+        let osc = ctx.create_oscillator()?;
+        osc.frequency()
+            .set_value(if accent { ACCENT_FREQ } else { BEAT_FREQ });
+        let gain = ctx.create_gain()?;
+        osc.connect_with_audio_node(&gain)?;
+        gain.connect_with_audio_node(&self.output)?;
+        gain.gain().set_value(0.0);
+        gain.gain()
+            .set_value_at_time(if accent { 1.0 } else { 0.6 }, time)?;
+        gain.gain()
+            .exponential_ramp_to_value_at_time(0.001, time + CLICK_LENGTH)?;
+        osc.start_with_when(time)?;
+        osc.stop_with_when(time + CLICK_LENGTH)?;
+        Ok(())
+    }
+}
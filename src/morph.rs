@@ -0,0 +1,102 @@
+use crate::error::{check_range, Error};
+use crate::sounds::SoundParams;
+use crate::step::{Sequence, Step, PATTERN_STEPS};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Blends pattern A into pattern B. Each step gets a fixed random threshold so
+/// that turning the amount up only ever moves steps from A over to B.
+pub struct Morph {
+    a: Sequence,
+    b: Sequence,
+    thresholds: [f32; PATTERN_STEPS],
+}
+
+impl Morph {
+    pub fn new(a: Sequence, b: Sequence, seed: u64) -> Morph {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut thresholds = [0.0; PATTERN_STEPS];
+        for threshold in thresholds.iter_mut() {
+            *threshold = rng.gen();
+        }
+        Morph { a, b, thresholds }
+    }
+
+    /// Returns the pattern at `amount`, where plain triggers stand for the
+    /// voice's current `base` parameters when they meet a locked step. The
+    /// ends are exactly pattern A and pattern B.
+    pub fn at(&self, amount: f32, base: SoundParams) -> Result<Sequence, Error> {
+        check_range("morph amount", amount.into(), 0.0, 1.0)?;
+        let mut sequence: Sequence = Default::default();
+        for (index, step) in sequence.iter_mut().enumerate() {
+            let (a, b) = (self.a[index], self.b[index]);
+            let chosen = if self.thresholds[index] < amount {
+                b
+            } else {
+                a
+            };
+            *step = match (chosen, a, b) {
+                _ if amount <= 0.0 => a,
+                _ if amount >= 1.0 => b,
+                (Step::Off, _, _) => Step::Off,
+                (_, Step::Trigger, Step::Trigger) => Step::Trigger,
+                (_, Step::Off, _) | (_, _, Step::Off) => chosen,
+                _ => match locked(a, base).lerp(&locked(b, base), amount) {
+                    Some(params) => Step::Lock { params },
+                    None => chosen,
+                },
+            };
+        }
+        Ok(sequence)
+    }
+}
+
+fn locked(step: Step, base: SoundParams) -> SoundParams {
+    match step {
+        Step::Lock { params } => params,
+        _ => base,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sounds::kick::KickParams;
+
+    fn kick(freq: f32) -> SoundParams {
+        SoundParams::Kick(KickParams {
+            freq,
+            ..KickParams::default()
+        })
+    }
+
+    #[test]
+    fn ends_match_the_source_patterns() {
+        let mut a: Sequence = Default::default();
+        let mut b: Sequence = Default::default();
+        a[0] = Step::Trigger;
+        a[3] = Step::Lock { params: kick(50.0) };
+        b[4] = Step::Trigger;
+        b[3] = Step::Trigger;
+        let morph = Morph::new(a, b, 1);
+        let base = kick(40.0);
+        assert_eq!(morph.at(0.0, base).unwrap(), a);
+        assert_eq!(morph.at(1.0, base).unwrap(), b);
+        let reversed = Morph::new(b, a, 1);
+        assert_eq!(reversed.at(0.0, base).unwrap(), b);
+        assert_eq!(reversed.at(1.0, base).unwrap(), a);
+    }
+
+    #[test]
+    fn interpolates_locked_parameters() {
+        let mut a: Sequence = Default::default();
+        let mut b: Sequence = Default::default();
+        a[0] = Step::Lock { params: kick(40.0) };
+        b[0] = Step::Lock { params: kick(80.0) };
+        let morph = Morph::new(a, b, 1);
+        assert_eq!(
+            morph.at(0.5, kick(40.0)).unwrap()[0],
+            Step::Lock { params: kick(60.0) }
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use crate::error::{check_range, Error};
+use crate::step::{Sequence, Step};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const HISTORY_LIMIT: usize = 64;
+
+#[derive(Copy, Clone, Debug)]
+pub struct MutationSettings {
+    pub flip_probability: f32,
+    pub param_amount: f32,
+    pub rotate_probability: f32,
+    pub every_bars: u32,
+}
+
+impl MutationSettings {
+    pub fn validate(&self) -> Result<(), Error> {
+        check_range("flip probability", self.flip_probability.into(), 0.0, 1.0)?;
+        check_range("param amount", self.param_amount.into(), 0.0, 1.0)?;
+        check_range(
+            "rotate probability",
+            self.rotate_probability.into(),
+            0.0,
+            1.0,
+        )?;
+        check_range("mutation interval", self.every_bars.into(), 1.0, 64.0)
+    }
+}
+
+pub struct Mutator {
+    pub settings: MutationSettings,
+    rng: StdRng,
+    last_bar: Option<u32>,
+    history: Vec<Vec<Sequence>>,
+}
+
+impl Mutator {
+    pub fn new(seed: u64, settings: MutationSettings) -> Self {
+        Mutator {
+            settings,
+            rng: StdRng::seed_from_u64(seed),
+            last_bar: None,
+            history: Vec::new(),
+        }
+    }
+
+    /// Forgets the last mutated bar so a restarted transport mutates again.
+    pub fn reset_clock(&mut self) {
+        self.last_bar = None;
+    }
+
+    pub fn is_due(&mut self, bar: u32) -> bool {
+        if bar == 0 || !bar.is_multiple_of(self.settings.every_bars) || self.last_bar == Some(bar) {
+            return false;
+        }
+        self.last_bar = Some(bar);
+        true
+    }
+
+    /// Mutates every track, given as `(sequence, length)` pairs, and keeps the
+    /// originals so the mutation can be undone. Tracks without a single step
+    /// on are left alone, so mutation never brings in a track nobody
+    /// programmed.
+    pub fn mutate(&mut self, tracks: &[(Sequence, i8)]) -> Vec<Sequence> {
+        if self.history.len() == HISTORY_LIMIT {
+            self.history.remove(0);
+        }
+        self.history
+            .push(tracks.iter().map(|(sequence, _)| *sequence).collect());
+        tracks
+            .iter()
+            .map(|(sequence, length)| self.mutate_sequence(sequence, *length as usize))
+            .collect()
+    }
+
+    pub fn undo(&mut self) -> Option<Vec<Sequence>> {
+        self.history.pop()
+    }
+
+    fn mutate_sequence(&mut self, sequence: &Sequence, length: usize) -> Sequence {
+        let mut mutated = *sequence;
+        if mutated.iter().all(|step| *step == Step::Off) {
+            return mutated;
+        }
+        for step in mutated.iter_mut().take(length) {
+            if self.rng.gen::<f32>() < self.settings.flip_probability {
+                *step = match step {
+                    Step::Off => Step::Trigger,
+                    _ => Step::Off,
+                };
+            }
+            if let Step::Lock { params } = step {
+                params.perturb(self.settings.param_amount, &mut self.rng);
+            }
+        }
+        if length > 1 && self.rng.gen::<f32>() < self.settings.rotate_probability {
+            if self.rng.gen::<bool>() {
+                mutated[..length].rotate_left(1);
+            } else {
+                mutated[..length].rotate_right(1);
+            }
+        }
+        mutated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sounds::kick::KickParams;
+    use crate::sounds::SoundParams;
+
+    fn settings() -> MutationSettings {
+        MutationSettings {
+            flip_probability: 0.3,
+            param_amount: 0.2,
+            rotate_probability: 0.5,
+            every_bars: 2,
+        }
+    }
+
+    fn tracks() -> Vec<(Sequence, i8)> {
+        let mut sequence: Sequence = Default::default();
+        sequence[0] = Step::Trigger;
+        sequence[8] = Step::Lock {
+            params: SoundParams::Kick(KickParams::default()),
+        };
+        vec![(sequence, 16), (sequence, 12)]
+    }
+
+    #[test]
+    fn same_seed_gives_same_mutations() {
+        let mut first = Mutator::new(7, settings());
+        let mut second = Mutator::new(7, settings());
+        for _ in 0..8 {
+            assert_eq!(first.mutate(&tracks()), second.mutate(&tracks()));
+        }
+    }
+
+    #[test]
+    fn mutations_stay_in_range_and_undo() {
+        let mut mutator = Mutator::new(3, settings());
+        let original = tracks();
+        let mut current = original.clone();
+        for _ in 0..32 {
+            let mutated = mutator.mutate(&current);
+            for (sequence, (_, length)) in mutated.iter().zip(&current) {
+                for (index, step) in sequence.iter().enumerate() {
+                    if index >= *length as usize {
+                        assert_eq!(*step, Step::Off);
+                    }
+                    if let Step::Lock { params } = step {
+                        assert!(params.validate().is_ok());
+                    }
+                }
+            }
+            current = mutated
+                .into_iter()
+                .zip(&current)
+                .map(|(s, (_, l))| (s, *l))
+                .collect();
+        }
+        let mut undone = None;
+        while let Some(previous) = mutator.undo() {
+            undone = Some(previous);
+        }
+        let originals: Vec<Sequence> = original.iter().map(|(sequence, _)| *sequence).collect();
+        assert_eq!(undone, Some(originals));
+    }
+
+    #[test]
+    fn leaves_empty_tracks_empty() {
+        let mut mutator = Mutator::new(5, settings());
+        mutator.settings.flip_probability = 1.0;
+        let empty: Sequence = Default::default();
+        let mut current = tracks();
+        current.push((empty, 16));
+        for _ in 0..8 {
+            let mutated = mutator.mutate(&current);
+            assert_eq!(mutated[2], empty);
+            assert_ne!(mutated[0], current[0].0);
+            current = mutated
+                .into_iter()
+                .zip(&current)
+                .map(|(s, (_, l))| (s, *l))
+                .collect();
+        }
+    }
+
+    #[test]
+    fn mutates_once_per_interval() {
+        let mut mutator = Mutator::new(1, settings());
+        let due: Vec<u32> = [0, 1, 2, 2, 3, 4]
+            .iter()
+            .copied()
+            .filter(|bar| mutator.is_due(*bar))
+            .collect();
+        assert_eq!(due, [2, 4]);
+    }
+}
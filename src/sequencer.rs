@@ -1,22 +1,178 @@
-use crate::sounds::{Sound, SoundParams};
+use crate::error::{check_range, Error};
+use crate::sounds::{Sound, SoundParams, Voice};
+use crate::step::{Sequence, Step};
+use crate::transport::{StepClock, Transport};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
 use wasm_bindgen::prelude::*;
 use web_sys::AudioContext;
 
 pub struct Sequencer {
-    pub sequence: [Option<Trigger<SoundParams>>; 16],
+    pub sequence: Sequence,
+    pub next_sequence: Option<Sequence>,
     trigger_times: [Option<f64>; 16],
     pub sound: Box<dyn Sound>,
     pub steps: i8,
     step_to_schedule: i8,
     step_playing: i8,
-    next_step_time: f64,
+    clock: StepClock,
     pub step_delta: f64,
-    pub offset: f64,
+    pub offset: Offset,
+    pub loops: u32,
+    loop_started_at: f64,
+    started_at: f64,
+    paused_at: Option<f64>,
+    pub loop_region: Option<(i8, i8)>,
+    pub humanize: Option<Humanize>,
+    pub choke_group: Option<u8>,
+    muted: bool,
+    pub stats: ScheduleStats,
+    voices: Vec<(i8, Voice)>,
+    events: Vec<StepEvent>,
 }
 
-pub enum Trigger<SoundParams> {
-    NormalTrigger,
-    LockTrigger(SoundParams),
+/// A step reaching the playhead, reported ahead of time with the audio clock
+/// time it is heard at.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct StepEvent {
+    pub track: &'static str,
+    pub step: i8,
+    pub time: f64,
+    pub bar: u32,
+    pub loop_count: u32,
+    pub hit: bool,
+}
+
+/// Takes the events heard before `until` out of `pending`, ordered by time.
+/// Humanized tracks schedule their steps early, so an event can still be
+/// followed by an earlier one from another track until every track has
+/// scheduled past it.
+pub fn release_events(pending: &mut Vec<StepEvent>, until: f64) -> Vec<StepEvent> {
+    pending.sort_by(|a, b| a.time.total_cmp(&b.time));
+    let ready = pending
+        .iter()
+        .take_while(|event| event.time < until)
+        .count();
+    pending.drain(..ready).collect()
+}
+
+/// What to do with hits whose time has already passed when they are
+/// scheduled, which happens when the page is throttled. Skipping is the
+/// default, since playing them all late would pile them up into one burst.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CatchUp {
+    Skip,
+    PlayLate,
+}
+
+impl std::str::FromStr for CatchUp {
+    type Err = Error;
+
+    fn from_str(policy: &str) -> Result<CatchUp, Error> {
+        match policy {
+            "skip" => Ok(CatchUp::Skip),
+            "late" => Ok(CatchUp::PlayLate),
+            _ => Err(Error::UnknownCatchUp(policy.to_string())),
+        }
+    }
+}
+
+/// How far ahead of the audio clock steps are scheduled and where their hits
+/// land. Every hit starts `margin` seconds after its step, so it is never
+/// scheduled in the past; `Audio::update_margin` sets it. `output_latency`
+/// is how much later the speaker plays it, and only moves the reported
+/// playhead.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Scheduling {
+    pub lookahead: f64,
+    pub catch_up: CatchUp,
+    pub margin: f64,
+    pub output_latency: f64,
+}
+
+impl Default for Scheduling {
+    fn default() -> Scheduling {
+        Scheduling {
+            lookahead: 0.04,
+            catch_up: CatchUp::Skip,
+            margin: 0.05,
+            output_latency: 0.0,
+        }
+    }
+}
+
+/// A track's shift against the grid, either as a fraction of its step, which
+/// follows the tempo, or in milliseconds.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Offset {
+    Steps(f64),
+    Millis(f64),
+}
+
+impl Offset {
+    /// Reads `amount` in `unit`, "steps" or "ms", clamped to half a step or
+    /// 100 ms either way. NaN and infinite amounts are rejected.
+    pub fn new(amount: f64, unit: &str) -> Result<Offset, Error> {
+        let (limit, offset): (f64, fn(f64) -> Offset) = match unit {
+            "steps" => (0.5, Offset::Steps),
+            "ms" => (100.0, Offset::Millis),
+            _ => return Err(Error::UnknownUnit(unit.to_string())),
+        };
+        if !amount.is_finite() {
+            return Err(Error::OutOfRange {
+                param: "offset",
+                value: amount,
+                min: -limit,
+                max: limit,
+            });
+        }
+        Ok(offset(amount.clamp(-limit, limit)))
+    }
+
+    /// The shift in seconds, never more than half of `step_delta` so that
+    /// hits keep their order.
+    pub fn seconds(&self, step_delta: f64) -> f64 {
+        let seconds = match self {
+            Offset::Steps(steps) => steps * step_delta,
+            Offset::Millis(millis) => millis / 1000.0,
+        };
+        seconds.clamp(-step_delta / 2.0, step_delta / 2.0)
+    }
+}
+
+/// Hits that were scheduled too late, either played anyway or skipped.
+#[derive(Serialize, Default, Debug, Copy, Clone, PartialEq)]
+pub struct ScheduleStats {
+    pub late: u32,
+    pub missed: u32,
+}
+
+/// Random per-hit deviation: `timing` is the largest shift as a fraction of a
+/// step and `velocity` the largest change in volume.
+pub struct Humanize {
+    pub timing: f64,
+    pub velocity: f32,
+    rng: StdRng,
+}
+
+impl Humanize {
+    pub fn new(timing: f64, velocity: f32, seed: u64) -> Result<Self, Error> {
+        check_range("humanize timing", timing, 0.0, 0.5)?;
+        check_range("humanize velocity", velocity.into(), 0.0, 1.0)?;
+        Ok(Humanize {
+            timing,
+            velocity,
+            rng: StdRng::seed_from_u64(seed),
+        })
+    }
+
+    /// Returns the time shift in steps and the volume factor for the next hit.
+    fn next_hit(&mut self) -> (f64, f32) {
+        let shift = self.timing * self.rng.gen_range(-1.0..=1.0);
+        let velocity = 1.0 + self.velocity * self.rng.gen_range(-1.0..=1.0);
+        (shift, velocity)
+    }
 }
 
 pub fn get_sequencer_steps(sequencer: &mut Sequencer, time: f64) -> i8 {
@@ -28,7 +184,7 @@ pub fn get_sequencer_steps(sequencer: &mut Sequencer, time: f64) -> i8 {
                 return step;
             }
         }
-        step = get_step(sequencer.step_to_schedule, sequencer.steps);
+        step = get_step(step, sequencer.steps);
     }
     sequencer.step_playing
 }
@@ -45,57 +201,484 @@ impl Sequencer {
     pub fn new(tempo: f32, sound: Box<dyn Sound>) -> Self {
         Sequencer {
             sequence: Default::default(),
+            next_sequence: None,
             trigger_times: Default::default(),
             sound,
             steps: 16,
             step_to_schedule: 0,
             step_playing: 0,
-            next_step_time: 0.0,
+            clock: StepClock::new(16, 4),
             step_delta: (60.0 / tempo as f64) * (4.0 / 16.0),
-            offset: 0.0,
+            offset: Offset::Steps(0.0),
+            loops: 0,
+            loop_started_at: 0.0,
+            started_at: 0.0,
+            paused_at: None,
+            loop_region: None,
+            humanize: None,
+            choke_group: None,
+            muted: false,
+            stats: Default::default(),
+            voices: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.sound.params().name()
+    }
+
+    /// How far before its step a humanized hit can start.
+    pub fn earliest(&self) -> f64 {
+        match &self.humanize {
+            Some(humanize) => humanize.timing * self.step_delta,
+            None => 0.0,
         }
     }
 
+    /// How much later than its first step the transport has to start for
+    /// that step's hit to start `margin` after the start, like an unshifted
+    /// hit. A negative offset and humanizing move the hit earlier.
+    pub fn lead(&self) -> f64 {
+        (self.earliest() - self.offset.seconds(self.step_delta)).max(0.0)
+    }
+
+    /// Schedules every step inside the lookahead window and returns the start
+    /// times of the hits it created.
     pub fn schedule_sounds(
         &mut self,
         ctx: &AudioContext,
-        schedule_interval: f32,
-    ) -> Result<(), JsValue> {
-        while self.next_step_time < ctx.current_time() + schedule_interval as f64 {
-            match &self.sequence[self.step_to_schedule as usize] {
-                Some(trigger) => match trigger {
-                    Trigger::LockTrigger(locked_sound) => {
-                        self.sound.play(
-                            ctx,
-                            Some(*locked_sound),
-                            self.next_step_time,
-                            self.offset,
-                        )?;
-                    }
-                    Trigger::NormalTrigger => {
-                        self.sound
-                            .play(ctx, None, self.next_step_time, self.offset)?;
+        transport: &Transport,
+        scheduling: &Scheduling,
+    ) -> Result<Vec<f64>, JsValue> {
+        self.schedule_until(
+            ctx.current_time(),
+            transport,
+            scheduling,
+            |sound, params, time| sound.play(ctx, Some(params), time),
+        )
+    }
+
+    /// Schedules the steps inside the lookahead window from `now`, handing
+    /// each hit to `play`.
+    fn schedule_until<F>(
+        &mut self,
+        now: f64,
+        transport: &Transport,
+        scheduling: &Scheduling,
+        mut play: F,
+    ) -> Result<Vec<f64>, JsValue>
+    where
+        F: FnMut(&dyn Sound, SoundParams, f64) -> Result<Option<Voice>, JsValue>,
+    {
+        self.voices.retain(|(_, voice)| voice.end > now);
+        let mut hits = Vec::new();
+        let offset = self.offset.seconds(self.step_delta);
+        // Humanized hits can land up to this much before their step, so
+        // steps are scheduled that much earlier.
+        let earliest = self.earliest();
+        while let Some(step_time) = transport.time_at(self.clock.beats()) {
+            if step_time + offset - earliest >= now + scheduling.lookahead {
+                break;
+            }
+            let start = step_time + offset + scheduling.margin;
+            let heard_at = start + scheduling.output_latency;
+            let mut hit = match self.sequence[self.step_to_schedule as usize] {
+                Step::Lock { params } => Some(params),
+                Step::Trigger => Some(self.sound.params()),
+                Step::Off => None,
+            }
+            .filter(|_| !self.muted)
+            .map(|mut params| {
+                let mut time = start;
+                if let Some(humanize) = &mut self.humanize {
+                    let (shift, velocity) = humanize.next_hit();
+                    time += shift * self.step_delta;
+                    params.scale_volume(velocity);
+                }
+                (params, time)
+            });
+            if let Some((_, time)) = hit {
+                if time < now {
+                    match scheduling.catch_up {
+                        CatchUp::Skip => {
+                            self.stats.missed += 1;
+                            hit = None;
+                        }
+                        CatchUp::PlayLate => self.stats.late += 1,
                     }
-                },
-                None => {}
+                }
+            }
+            let voice = match hit {
+                Some((params, time)) => play(self.sound.as_ref(), params, time.max(now))?,
+                None => None,
+            };
+            let hit = voice.is_some();
+            if let Some(voice) = voice {
+                hits.push(voice.start);
+                self.voices.push((self.step_to_schedule, voice));
+            }
+            self.events.push(StepEvent {
+                track: self.name(),
+                step: self.step_to_schedule,
+                time: heard_at,
+                bar: transport.bar_at(step_time),
+                loop_count: self.loops,
+                hit,
+            });
+            self.trigger_times[self.step_to_schedule as usize] = Some(heard_at);
+            self.next_step();
+        }
+        Ok(hits)
+    }
+
+    /// Moves on to the next step, wrapping at the end of the loop.
+    fn next_step(&mut self) {
+        self.clock.advance();
+        self.step_to_schedule += 1;
+        self.wrap();
+    }
+
+    fn wrap(&mut self) {
+        let (first, end) = self.loop_bounds();
+        if self.step_to_schedule >= end {
+            self.step_to_schedule = first;
+            self.loops += 1;
+            self.loop_started_at = self.clock.beats();
+            if let Some(sequence) = self.next_sequence.take() {
+                self.sequence = sequence;
+            }
+        }
+    }
+
+    pub fn take_events(&mut self) -> Vec<StepEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    pub fn choke(&mut self, time: f64) -> Result<(), JsValue> {
+        for (_, voice) in self.voices.iter_mut() {
+            if voice.is_ringing(time) {
+                voice.choke(time)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops the hits on steps matching `step` that have not started by `time`.
+    fn cancel<F: Fn(i8) -> bool>(&mut self, time: f64, step: F) -> Result<(), JsValue> {
+        for (voice_step, voice) in self.voices.iter_mut() {
+            if voice.start > time && step(*voice_step) {
+                voice.choke(time)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fades out everything still ringing or waiting to play at `time`.
+    pub fn silence(&mut self, time: f64) -> Result<(), JsValue> {
+        for (_, voice) in self.voices.iter_mut() {
+            if voice.end > time {
+                voice.choke(time)?;
             }
-            self.trigger_times[self.step_to_schedule as usize] = Some(self.next_step_time);
-            self.step_to_schedule += 1;
-            if self.step_to_schedule >= self.steps {
-                self.step_to_schedule = 0;
+        }
+        Ok(())
+    }
+
+    /// Mutes the track, dropping hits that were already scheduled.
+    pub fn set_muted(&mut self, muted: bool, time: f64) -> Result<(), JsValue> {
+        self.muted = muted;
+        if muted {
+            self.cancel(time, |_| true)?;
+        }
+        Ok(())
+    }
+
+    pub fn set_timing(&mut self, bar_duration: f64, beats_per_bar: u8) {
+        self.step_delta = bar_duration / self.steps as f64;
+        if let Some(step) = self.clock.retune(self.steps, beats_per_bar) {
+            // The clock counts on through a loop region, so its step can fall
+            // before the region.
+            let (first, _) = self.loop_bounds();
+            self.step_to_schedule = (step as i8).max(first);
+            self.wrap();
+        }
+    }
+
+    /// Number of completed loops at transport position `beats`, which may be
+    /// behind the loop currently being scheduled.
+    pub fn loops_at(&self, beats: f64) -> u32 {
+        if beats < self.loop_started_at {
+            self.loops.saturating_sub(1)
+        } else {
+            self.loops
+        }
+    }
+
+    /// Replaces the pattern, cancelling scheduled hits on steps it turns off.
+    pub fn set_sequence(&mut self, sequence: Sequence, time: f64) -> Result<(), JsValue> {
+        self.sequence = sequence;
+        self.next_sequence = None;
+        self.cancel(time, |step| sequence[step as usize] == Step::Off)
+    }
+
+    /// First step and the step after the last one that the track loops over.
+    fn loop_bounds(&self) -> (i8, i8) {
+        match self.loop_region {
+            Some((first, last)) => {
+                let first = first.min(self.steps - 1);
+                (first, (last + 1).min(self.steps).max(first + 1))
+            }
+            None => (0, self.steps),
+        }
+    }
+
+    /// Starts at `into_bar` beats into bar number `bar` of the transport.
+    pub fn play(&mut self, bar: u32, beats_per_bar: u8, into_bar: f64) {
+        let bar_start = (bar * beats_per_bar as u32) as f64;
+        self.loop_started_at = bar_start;
+        self.loops = bar;
+        self.step_to_schedule = self.clock.start(bar_start, bar_start + into_bar) as i8;
+        self.started_at = self.clock.beats();
+        let (first, end) = self.loop_bounds();
+        if self.step_to_schedule < first || self.step_to_schedule >= end {
+            self.step_to_schedule = first;
+        }
+        self.paused_at = None;
+    }
+
+    /// Rewinds to the first step whose hit has not started at `time`, so that
+    /// `resume` picks up exactly where playback left off. Hits start `margin`
+    /// plus the offset after their step, so the transport is frozen at
+    /// `time - margin` for them to keep that lead. Never rewinds past the
+    /// step playback started on, which a pause during a count-in would.
+    pub fn pause(&mut self, time: f64, transport: &Transport, margin: f64) -> Result<(), JsValue> {
+        self.cancel(time, |_| true)?;
+        let offset = self.offset.seconds(self.step_delta);
+        let beats = transport.beats_at(time - margin - offset);
+        while self.clock.previous_beats() >= beats.max(self.started_at) {
+            let (first, end) = self.loop_bounds();
+            if self.step_to_schedule == first {
+                self.step_to_schedule = end - 1;
+                self.loops = self.loops.saturating_sub(1);
+            } else {
+                self.step_to_schedule -= 1;
             }
-            self.next_step_time += self.step_delta;
+            self.clock.rewind();
+            self.trigger_times[self.step_to_schedule as usize] = None;
         }
+        self.paused_at = Some(time - margin);
         Ok(())
     }
 
-    pub fn play(&mut self, ctx: &AudioContext) {
-        self.next_step_time = ctx.current_time();
+    /// Moves the playhead's record of heard steps past the pause, given the
+    /// time the transport picks up again.
+    pub fn resume(&mut self, time: f64) {
+        let paused_at = match self.paused_at.take() {
+            Some(paused_at) => paused_at,
+            None => return,
+        };
+        let delta = time - paused_at;
+        for trigger_time in self.trigger_times.iter_mut().flatten() {
+            *trigger_time += delta;
+        }
     }
 
     pub fn stop(&mut self) {
         self.step_to_schedule = 0;
         self.step_playing = 0;
         self.trigger_times = Default::default();
+        self.paused_at = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Silent;
+
+    impl Sound for Silent {
+        fn play(
+            &self,
+            _ctx: &AudioContext,
+            _params: Option<SoundParams>,
+            _time: f64,
+        ) -> Result<Option<Voice>, JsValue> {
+            unreachable!("the tests never schedule audio")
+        }
+        fn params(&self) -> SoundParams {
+            SoundParams::Kick(Default::default())
+        }
+        fn update(&mut self, _params: SoundParams) {}
+        fn update_volume(&mut self, _ctx: &AudioContext, _volume: f32) -> Result<(), JsValue> {
+            Ok(())
+        }
+    }
+
+    /// A 16 step track at 120 BPM, so steps are 0.125 s apart, with its
+    /// first `scheduled` steps already scheduled.
+    fn playing(transport: &mut Transport, scheduled: usize) -> Sequencer {
+        let mut sequencer = Sequencer::new(120.0, Box::new(Silent));
+        sequencer.play(0, 4, 0.0);
+        transport.start(0.0, 0.0);
+        for _ in 0..scheduled {
+            sequencer.next_step();
+        }
+        sequencer
+    }
+
+    #[test]
+    fn pause_keeps_steps_that_have_not_been_heard() {
+        let mut transport = Transport::new(120.0);
+        let mut sequencer = playing(&mut transport, 3);
+        // Step 2 is due at 0.25 s but starts at 0.3 s with the margin.
+        sequencer.pause(0.27, &transport, 0.05).unwrap();
+        assert_eq!(sequencer.step_to_schedule, 2);
+
+        let mut sequencer = playing(&mut transport, 3);
+        sequencer.pause(0.31, &transport, 0.05).unwrap();
+        assert_eq!(sequencer.step_to_schedule, 3);
+    }
+
+    #[test]
+    fn resume_keeps_the_lead_of_a_hit_cut_at_pause() {
+        let mut transport = Transport::new(120.0);
+        let mut sequencer = playing(&mut transport, 3);
+        sequencer.sequence[2] = Step::Trigger;
+        // Step 2's hit would have started at 0.3 s.
+        sequencer.pause(0.299, &transport, 0.05).unwrap();
+        transport.pause(0.299 - 0.05);
+        sequencer.resume(10.0);
+        transport.resume(10.0);
+        let times = hits(&mut sequencer, &transport, 10.02);
+        assert_eq!(times.len(), 1);
+        assert!(times[0] >= 10.05 - 1e-9);
+        assert_eq!(sequencer.stats, ScheduleStats::default());
+    }
+
+    #[test]
+    fn pause_during_a_count_in_stays_on_the_first_step() {
+        let mut transport = Transport::new(120.0);
+        let mut sequencer = Sequencer::new(120.0, Box::new(Silent));
+        sequencer.play(0, 4, 0.0);
+        transport.start(0.0, -4.0);
+        sequencer.pause(0.75, &transport, 0.05).unwrap();
+        assert_eq!(sequencer.step_to_schedule, 0);
+        assert_eq!(sequencer.clock.beats(), 0.0);
+    }
+
+    /// Schedules from `now` and returns the start times of the hits.
+    fn hits(sequencer: &mut Sequencer, transport: &Transport, now: f64) -> Vec<f64> {
+        let mut times = Vec::new();
+        sequencer
+            .schedule_until(now, transport, &Scheduling::default(), |_, _, time| {
+                times.push(time);
+                Ok(None)
+            })
+            .unwrap();
+        times
+    }
+
+    #[test]
+    fn early_offsets_keep_the_first_hit() {
+        let mut transport = Transport::new(90.0);
+        let mut sequencer = Sequencer::new(90.0, Box::new(Silent));
+        sequencer.sequence[0] = Step::Trigger;
+        sequencer.offset = Offset::new(-0.5, "steps").unwrap();
+        sequencer.play(0, 4, 0.0);
+        transport.start(1.0 + sequencer.lead(), 0.0);
+        let times = hits(&mut sequencer, &transport, 1.02);
+        assert_eq!(times.len(), 1);
+        assert!((times[0] - 1.05).abs() < 1e-9);
+        assert_eq!(sequencer.stats, ScheduleStats::default());
+    }
+
+    #[test]
+    fn early_humanized_hits_keep_the_first_hit() {
+        for seed in 0..32 {
+            let mut transport = Transport::new(90.0);
+            let mut sequencer = Sequencer::new(90.0, Box::new(Silent));
+            sequencer.sequence[0] = Step::Trigger;
+            sequencer.humanize = Some(Humanize::new(0.5, 0.0, seed).unwrap());
+            sequencer.play(0, 4, 0.0);
+            transport.start(1.0 + sequencer.lead(), 0.0);
+            let times = hits(&mut sequencer, &transport, 1.02);
+            assert_eq!(times.len(), 1, "seed {}", seed);
+            assert!(times[0] >= 1.05 - 1e-9, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn changing_length_mid_bar_keeps_the_bar_lines() {
+        let mut transport = Transport::new(120.0);
+        let mut sequencer = playing(&mut transport, 5);
+        sequencer.steps = 12;
+        sequencer.set_timing(transport.bar_duration(), 4);
+        assert_eq!(sequencer.step_to_schedule, 4);
+        while sequencer.step_to_schedule != 0 {
+            sequencer.next_step();
+        }
+        assert_eq!((sequencer.loops, sequencer.clock.beats()), (1, 4.0));
+    }
+
+    #[test]
+    fn changing_length_stays_inside_the_loop_region() {
+        let mut transport = Transport::new(120.0);
+        let mut sequencer = Sequencer::new(120.0, Box::new(Silent));
+        sequencer.loop_region = Some((4, 7));
+        sequencer.play(0, 4, 0.0);
+        transport.start(0.0, 0.0);
+        for _ in 0..2 {
+            sequencer.next_step();
+        }
+        assert_eq!(sequencer.step_to_schedule, 6);
+        sequencer.steps = 12;
+        sequencer.set_timing(transport.bar_duration(), 4);
+        for _ in 0..16 {
+            assert!((4..=7).contains(&sequencer.step_to_schedule));
+            sequencer.next_step();
+        }
+    }
+
+    #[test]
+    fn events_are_released_in_order() {
+        let event = |step: i8, time: f64| StepEvent {
+            track: "kick",
+            step,
+            time,
+            bar: 0,
+            loop_count: 0,
+            hit: true,
+        };
+        let mut pending = vec![event(1, 0.3), event(0, 0.1)];
+        assert_eq!(release_events(&mut pending, 0.2), [event(0, 0.1)]);
+        pending.push(event(2, 0.25));
+        assert_eq!(
+            release_events(&mut pending, 0.4),
+            [event(2, 0.25), event(1, 0.3)]
+        );
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn step_offsets_follow_the_tempo() {
+        let offset = Offset::new(0.25, "steps").unwrap();
+        assert_eq!(offset.seconds(0.2), 0.05);
+        assert_eq!(offset.seconds(0.1), 0.025);
+        assert_eq!(Offset::new(-20.0, "ms").unwrap().seconds(0.2), -0.02);
+    }
+
+    #[test]
+    fn offsets_are_clamped() {
+        assert_eq!(Offset::new(2.0, "steps").unwrap(), Offset::Steps(0.5));
+        assert_eq!(Offset::new(-100.0, "ms").unwrap().seconds(0.1), -0.05);
+        assert!(Offset::new(1.0, "beats").is_err());
+    }
+
+    #[test]
+    fn offsets_must_be_finite() {
+        assert!(Offset::new(f64::NAN, "ms").is_err());
+        assert!(Offset::new(f64::INFINITY, "steps").is_err());
+        assert!(Offset::new(f64::NEG_INFINITY, "ms").is_err());
     }
 }
@@ -0,0 +1,163 @@
+use super::{lerp, ParamRange};
+use crate::error::Error;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use web_sys::{AudioBuffer, AudioContext, AudioScheduledSourceNode, BiquadFilterType};
+
+const BURSTS: u32 = 3;
+
+pub struct Clap {
+    nodes: ClapNodes,
+    pub params: ClapParams,
+}
+
+/// `tone` is the centre of the bandpass filter and `spread` the time between
+/// the hand claps that make up one hit.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct ClapParams {
+    pub tone: f32,
+    pub spread: f32,
+    pub decay: f32,
+    pub punch: f32,
+    pub volume: f32,
+}
+
+struct ClapNodes {
+    noise_buffer: AudioBuffer,
+    volume: web_sys::GainNode,
+}
+
+impl Default for ClapParams {
+    fn default() -> ClapParams {
+        ClapParams {
+            tone: 1200.0,
+            spread: 0.01,
+            decay: 0.3,
+            punch: 0.5,
+            volume: 0.7,
+        }
+    }
+}
+
+const TONE: ParamRange = ParamRange::new("clap tone", 200.0, 8000.0);
+const SPREAD: ParamRange = ParamRange::new("clap spread", 0.002, 0.05);
+const DECAY: ParamRange = ParamRange::new("clap decay", 0.01, 4.0);
+const PUNCH: ParamRange = ParamRange::new("clap punch", 0.0, 2.0);
+const VOLUME: ParamRange = ParamRange::new("clap volume", 0.0, 1.0);
+
+impl ClapParams {
+    pub fn validate(&self) -> Result<(), Error> {
+        TONE.check(self.tone)?;
+        SPREAD.check(self.spread)?;
+        DECAY.check(self.decay)?;
+        PUNCH.check(self.punch)?;
+        VOLUME.check(self.volume)
+    }
+
+    pub fn perturb<R: Rng + ?Sized>(&mut self, amount: f32, rng: &mut R) {
+        self.tone = TONE.perturb(self.tone, amount, rng);
+        self.spread = SPREAD.perturb(self.spread, amount, rng);
+        self.decay = DECAY.perturb(self.decay, amount, rng);
+        self.punch = PUNCH.perturb(self.punch, amount, rng);
+        self.volume = VOLUME.perturb(self.volume, amount, rng);
+    }
+
+    pub fn lerp(&self, other: &ClapParams, amount: f32) -> ClapParams {
+        ClapParams {
+            tone: lerp(self.tone, other.tone, amount),
+            spread: lerp(self.spread, other.spread, amount),
+            decay: lerp(self.decay, other.decay, amount),
+            punch: lerp(self.punch, other.punch, amount),
+            volume: lerp(self.volume, other.volume, amount),
+        }
+    }
+}
+
+impl Clap {
+    pub fn new(ctx: &AudioContext) -> Result<Self, JsValue> {
+        let noise_buffer = super::noise_buffer(ctx)?;
+        let volume = ctx.create_gain()?;
+        volume.gain().set_value(0.9);
+        volume.connect_with_audio_node(&ctx.destination())?;
+        Ok(Clap {
+            params: ClapParams::default(),
+            nodes: ClapNodes {
+                noise_buffer,
+                volume,
+            },
+        })
+    }
+}
+
+impl super::Sound for Clap {
+    fn update_volume(&mut self, ctx: &AudioContext, volume: f32) -> Result<(), JsValue> {
+        self.nodes
+            .volume
+            .gain()
+            .linear_ramp_to_value_at_time(volume, ctx.current_time() + 0.01)?;
+        Ok(())
+    }
+    fn params(&self) -> super::SoundParams {
+        super::SoundParams::Clap(self.params)
+    }
+    fn update(&mut self, params: super::SoundParams) {
+        if let super::SoundParams::Clap(clap_params) = params {
+            self.params = clap_params;
+        }
+    }
+    fn play(
+        &self,
+        ctx: &AudioContext,
+        clap_params: Option<super::SoundParams>,
+        time: f64,
+    ) -> Result<Option<super::Voice>, JsValue> {
+        let params = match clap_params {
+            Some(super::SoundParams::Clap(params)) => params,
+            _ => self.params,
+        };
+        let white_noise = ctx.create_buffer_source()?;
+        white_noise.set_buffer(Some(&self.nodes.noise_buffer));
+        white_noise.set_loop(true);
+
+        let filter = ctx.create_biquad_filter()?;
+        filter.set_type(BiquadFilterType::Bandpass);
+        filter.frequency().set_value(params.tone);
+        filter.q().set_value(1.5);
+        let gain = ctx.create_gain()?;
+        let compressor = ctx.create_dynamics_compressor()?;
+        compressor.threshold().set_value(-30.0 * params.punch);
+        compressor.knee().set_value(1.0);
+        compressor.ratio().set_value(5.0);
+        compressor.attack().set_value(0.1);
+        compressor.release().set_value(0.1);
+
+        white_noise.connect_with_audio_node(&filter)?;
+        filter.connect_with_audio_node(&gain)?;
+        gain.connect_with_audio_node(&compressor)?;
+        compressor.connect_with_audio_node(&self.nodes.volume)?;
+
+        // A few quick bursts, then a longer diffuse tail from the last one.
+        let spread = params.spread as f64;
+        let peak = 0.5 * params.volume;
+        gain.gain().set_value(0.0);
+        for burst in 0..BURSTS {
+            let start = time + burst as f64 * spread;
+            gain.gain().set_value_at_time(peak, start)?;
+            gain.gain().set_target_at_time(0.0, start, spread / 3.0)?;
+        }
+        let tail = time + BURSTS as f64 * spread;
+        gain.gain().set_value_at_time(peak * 0.6, tail)?;
+        gain.gain()
+            .set_target_at_time(0.0, tail, (params.decay / 3.0) as f64)?;
+        let noise_source: AudioScheduledSourceNode = white_noise.into();
+        noise_source.start()?;
+        noise_source.stop_with_when(time + 4.0)?;
+        Ok(Some(super::Voice {
+            gain,
+            sources: vec![noise_source],
+            start: time,
+            end: time + 4.0,
+        }))
+    }
+}
@@ -0,0 +1,140 @@
+use super::{lerp, ParamRange};
+use crate::error::Error;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use web_sys::{AudioContext, AudioScheduledSourceNode, BiquadFilterType, OscillatorType};
+
+/// Ratio between the two oscillators, as on the TR-808 (540 Hz and 800 Hz).
+const DETUNE: f32 = 800.0 / 540.0;
+
+/// `freq` is the lower of the two square waves and `tone` the centre of the
+/// bandpass they go through.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct CowbellParams {
+    pub freq: f32,
+    pub tone: f32,
+    pub decay: f32,
+    pub volume: f32,
+}
+
+pub struct Cowbell {
+    nodes: CowbellNodes,
+    pub params: CowbellParams,
+}
+
+struct CowbellNodes {
+    volume: web_sys::GainNode,
+}
+
+impl Default for CowbellParams {
+    fn default() -> CowbellParams {
+        CowbellParams {
+            freq: 540.0,
+            tone: 2640.0,
+            decay: 0.15,
+            volume: 0.7,
+        }
+    }
+}
+
+const FREQ: ParamRange = ParamRange::new("cowbell freq", 200.0, 1500.0);
+const TONE: ParamRange = ParamRange::new("cowbell tone", 500.0, 8000.0);
+const DECAY: ParamRange = ParamRange::new("cowbell decay", 0.01, 2.0);
+const VOLUME: ParamRange = ParamRange::new("cowbell volume", 0.0, 1.0);
+
+impl CowbellParams {
+    pub fn validate(&self) -> Result<(), Error> {
+        FREQ.check(self.freq)?;
+        TONE.check(self.tone)?;
+        DECAY.check(self.decay)?;
+        VOLUME.check(self.volume)
+    }
+
+    pub fn perturb<R: Rng + ?Sized>(&mut self, amount: f32, rng: &mut R) {
+        self.freq = FREQ.perturb(self.freq, amount, rng);
+        self.tone = TONE.perturb(self.tone, amount, rng);
+        self.decay = DECAY.perturb(self.decay, amount, rng);
+        self.volume = VOLUME.perturb(self.volume, amount, rng);
+    }
+
+    pub fn lerp(&self, other: &CowbellParams, amount: f32) -> CowbellParams {
+        CowbellParams {
+            freq: lerp(self.freq, other.freq, amount),
+            tone: lerp(self.tone, other.tone, amount),
+            decay: lerp(self.decay, other.decay, amount),
+            volume: lerp(self.volume, other.volume, amount),
+        }
+    }
+}
+
+impl Cowbell {
+    pub fn new(ctx: &AudioContext) -> Result<Self, JsValue> {
+        let volume = ctx.create_gain()?;
+        volume.gain().set_value(0.9);
+        volume.connect_with_audio_node(&ctx.destination())?;
+        Ok(Cowbell {
+            params: CowbellParams::default(),
+            nodes: CowbellNodes { volume },
+        })
+    }
+}
+
+impl super::Sound for Cowbell {
+    fn update_volume(&mut self, ctx: &AudioContext, volume: f32) -> Result<(), JsValue> {
+        self.nodes
+            .volume
+            .gain()
+            .linear_ramp_to_value_at_time(volume, ctx.current_time() + 0.01)?;
+        Ok(())
+    }
+    fn params(&self) -> super::SoundParams {
+        super::SoundParams::Cowbell(self.params)
+    }
+    fn update(&mut self, params: super::SoundParams) {
+        if let super::SoundParams::Cowbell(cowbell_params) = params {
+            self.params = cowbell_params;
+        }
+    }
+    fn play(
+        &self,
+        ctx: &AudioContext,
+        cowbell_params: Option<super::SoundParams>,
+        time: f64,
+    ) -> Result<Option<super::Voice>, JsValue> {
+        let params = match cowbell_params {
+            Some(super::SoundParams::Cowbell(params)) => params,
+            _ => self.params,
+        };
+        let filter = ctx.create_biquad_filter()?;
+        filter.set_type(BiquadFilterType::Bandpass);
+        filter.frequency().set_value(params.tone);
+        filter.q().set_value(1.0);
+        let gain = ctx.create_gain()?;
+        filter.connect_with_audio_node(&gain)?;
+        gain.connect_with_audio_node(&self.nodes.volume)?;
+
+        let mut sources: Vec<AudioScheduledSourceNode> = Vec::new();
+        for freq in [params.freq, params.freq * DETUNE].iter() {
+            let osc = ctx.create_oscillator()?;
+            osc.set_type(OscillatorType::Square);
+            osc.frequency().set_value(*freq);
+            osc.connect_with_audio_node(&filter)?;
+            osc.start()?;
+            osc.stop_with_when(time + 4.0)?;
+            sources.push(osc.into());
+        }
+
+        gain.gain().set_value(0.0);
+        gain.gain()
+            .set_target_at_time(0.3 * params.volume, time, 0.0005)?;
+        gain.gain()
+            .set_target_at_time(0.0, time + 0.005, (params.decay / 3.0) as f64)?;
+        Ok(Some(super::Voice {
+            gain,
+            sources,
+            start: time,
+            end: time + 4.0,
+        }))
+    }
+}
@@ -0,0 +1,156 @@
+use super::{lerp, ParamRange};
+use crate::error::Error;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use web_sys::{AudioContext, AudioScheduledSourceNode, BiquadFilterType, OscillatorType};
+
+/// The six square wave pitches of the TR-808 cymbal and hats.
+const METAL_FREQS: [f32; 6] = [205.3, 304.4, 369.6, 522.7, 540.0, 800.0];
+
+/// Metallic hat and cymbal. `tone` sets the bandpass centre, and each hit
+/// rings for `closed_decay` or, when `open` is set, `open_decay` seconds.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct CymbalParams {
+    pub tone: f32,
+    pub closed_decay: f32,
+    pub open_decay: f32,
+    pub open: bool,
+    pub volume: f32,
+}
+
+pub struct Cymbal {
+    nodes: CymbalNodes,
+    pub params: CymbalParams,
+}
+
+struct CymbalNodes {
+    volume: web_sys::GainNode,
+}
+
+impl Default for CymbalParams {
+    fn default() -> CymbalParams {
+        CymbalParams {
+            tone: 10000.0,
+            closed_decay: 0.05,
+            open_decay: 0.4,
+            open: false,
+            volume: 0.7,
+        }
+    }
+}
+
+const TONE: ParamRange = ParamRange::new("cymbal tone", 2000.0, 16000.0);
+const CLOSED_DECAY: ParamRange = ParamRange::new("cymbal closed decay", 0.01, 0.5);
+const OPEN_DECAY: ParamRange = ParamRange::new("cymbal open decay", 0.05, 4.0);
+const VOLUME: ParamRange = ParamRange::new("cymbal volume", 0.0, 1.0);
+
+impl CymbalParams {
+    pub fn validate(&self) -> Result<(), Error> {
+        TONE.check(self.tone)?;
+        CLOSED_DECAY.check(self.closed_decay)?;
+        OPEN_DECAY.check(self.open_decay)?;
+        VOLUME.check(self.volume)
+    }
+
+    pub fn perturb<R: Rng + ?Sized>(&mut self, amount: f32, rng: &mut R) {
+        self.tone = TONE.perturb(self.tone, amount, rng);
+        self.closed_decay = CLOSED_DECAY.perturb(self.closed_decay, amount, rng);
+        self.open_decay = OPEN_DECAY.perturb(self.open_decay, amount, rng);
+        self.volume = VOLUME.perturb(self.volume, amount, rng);
+    }
+
+    pub fn lerp(&self, other: &CymbalParams, amount: f32) -> CymbalParams {
+        CymbalParams {
+            tone: lerp(self.tone, other.tone, amount),
+            closed_decay: lerp(self.closed_decay, other.closed_decay, amount),
+            open_decay: lerp(self.open_decay, other.open_decay, amount),
+            open: if amount < 0.5 { self.open } else { other.open },
+            volume: lerp(self.volume, other.volume, amount),
+        }
+    }
+
+    pub fn decay(&self) -> f32 {
+        if self.open {
+            self.open_decay
+        } else {
+            self.closed_decay
+        }
+    }
+}
+
+impl Cymbal {
+    pub fn new(ctx: &AudioContext) -> Result<Self, JsValue> {
+        let volume = ctx.create_gain()?;
+        volume.gain().set_value(0.9);
+        volume.connect_with_audio_node(&ctx.destination())?;
+        Ok(Cymbal {
+            params: CymbalParams::default(),
+            nodes: CymbalNodes { volume },
+        })
+    }
+}
+
+impl super::Sound for Cymbal {
+    fn update_volume(&mut self, ctx: &AudioContext, volume: f32) -> Result<(), JsValue> {
+        self.nodes
+            .volume
+            .gain()
+            .linear_ramp_to_value_at_time(volume, ctx.current_time() + 0.01)?;
+        Ok(())
+    }
+    fn params(&self) -> super::SoundParams {
+        super::SoundParams::Cymbal(self.params)
+    }
+    fn update(&mut self, params: super::SoundParams) {
+        if let super::SoundParams::Cymbal(cymbal_params) = params {
+            self.params = cymbal_params;
+        }
+    }
+    fn play(
+        &self,
+        ctx: &AudioContext,
+        cymbal_params: Option<super::SoundParams>,
+        time: f64,
+    ) -> Result<Option<super::Voice>, JsValue> {
+        let params = match cymbal_params {
+            Some(super::SoundParams::Cymbal(params)) => params,
+            _ => self.params,
+        };
+        let bandpass = ctx.create_biquad_filter()?;
+        bandpass.set_type(BiquadFilterType::Bandpass);
+        bandpass.frequency().set_value(params.tone);
+        bandpass.q().set_value(0.8);
+        let highpass = ctx.create_biquad_filter()?;
+        highpass.set_type(BiquadFilterType::Highpass);
+        highpass.frequency().set_value(params.tone * 0.7);
+        let gain = ctx.create_gain()?;
+        bandpass.connect_with_audio_node(&highpass)?;
+        highpass.connect_with_audio_node(&gain)?;
+        gain.connect_with_audio_node(&self.nodes.volume)?;
+
+        let mut sources: Vec<AudioScheduledSourceNode> = Vec::new();
+        for freq in METAL_FREQS.iter() {
+            let osc = ctx.create_oscillator()?;
+            osc.set_type(OscillatorType::Square);
+            osc.frequency().set_value(*freq);
+            osc.connect_with_audio_node(&bandpass)?;
+            osc.start()?;
+            osc.stop_with_when(time + 4.0)?;
+            sources.push(osc.into());
+        }
+
+        gain.gain().set_value(0.0);
+        gain.gain()
+            .set_target_at_time(0.3 * params.volume, time, 0.0005)?;
+        let decay = params.decay() as f64;
+        gain.gain()
+            .set_target_at_time(0.0, time + 0.002, decay / 3.0)?;
+        Ok(Some(super::Voice {
+            gain,
+            sources,
+            start: time,
+            end: time + 4.0,
+        }))
+    }
+}
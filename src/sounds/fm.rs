@@ -0,0 +1,174 @@
+use super::{lerp, ParamRange};
+use crate::error::Error;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use web_sys::AudioContext;
+
+/// Two-operator FM. A sine modulator at `freq * ratio` drives the carrier's
+/// frequency; `index` is the peak modulation depth, in multiples of the
+/// modulator frequency, and falls away over `index_decay` seconds so hits
+/// start bright and settle.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct FmParams {
+    pub freq: f32,
+    pub ratio: f32,
+    pub index: f32,
+    pub index_decay: f32,
+    pub decay: f32,
+    pub volume: f32,
+}
+
+pub struct Fm {
+    nodes: FmNodes,
+    pub params: FmParams,
+}
+
+struct FmNodes {
+    volume: web_sys::GainNode,
+}
+
+impl Default for FmParams {
+    fn default() -> FmParams {
+        FmParams {
+            freq: 200.0,
+            ratio: 1.4,
+            index: 5.0,
+            index_decay: 0.1,
+            decay: 0.3,
+            volume: 0.7,
+        }
+    }
+}
+
+const FREQ: ParamRange = ParamRange::new("fm freq", 20.0, 2000.0);
+const RATIO: ParamRange = ParamRange::new("fm ratio", 0.25, 16.0);
+const INDEX: ParamRange = ParamRange::new("fm index", 0.0, 20.0);
+const INDEX_DECAY: ParamRange = ParamRange::new("fm index decay", 0.005, 2.0);
+const DECAY: ParamRange = ParamRange::new("fm decay", 0.01, 4.0);
+const VOLUME: ParamRange = ParamRange::new("fm volume", 0.0, 1.0);
+
+impl FmParams {
+    pub fn validate(&self) -> Result<(), Error> {
+        FREQ.check(self.freq)?;
+        RATIO.check(self.ratio)?;
+        INDEX.check(self.index)?;
+        INDEX_DECAY.check(self.index_decay)?;
+        DECAY.check(self.decay)?;
+        VOLUME.check(self.volume)
+    }
+
+    pub fn perturb<R: Rng + ?Sized>(&mut self, amount: f32, rng: &mut R) {
+        self.freq = FREQ.perturb(self.freq, amount, rng);
+        self.ratio = RATIO.perturb(self.ratio, amount, rng);
+        self.index = INDEX.perturb(self.index, amount, rng);
+        self.index_decay = INDEX_DECAY.perturb(self.index_decay, amount, rng);
+        self.decay = DECAY.perturb(self.decay, amount, rng);
+        self.volume = VOLUME.perturb(self.volume, amount, rng);
+    }
+
+    pub fn lerp(&self, other: &FmParams, amount: f32) -> FmParams {
+        FmParams {
+            freq: lerp(self.freq, other.freq, amount),
+            ratio: lerp(self.ratio, other.ratio, amount),
+            index: lerp(self.index, other.index, amount),
+            index_decay: lerp(self.index_decay, other.index_decay, amount),
+            decay: lerp(self.decay, other.decay, amount),
+            volume: lerp(self.volume, other.volume, amount),
+        }
+    }
+
+    pub fn modulator_freq(&self) -> f32 {
+        self.freq * self.ratio
+    }
+}
+
+impl Fm {
+    pub fn new(ctx: &AudioContext) -> Result<Self, JsValue> {
+        let volume = ctx.create_gain()?;
+        volume.gain().set_value(0.9);
+        volume.connect_with_audio_node(&ctx.destination())?;
+        Ok(Fm {
+            params: FmParams::default(),
+            nodes: FmNodes { volume },
+        })
+    }
+}
+
+impl super::Sound for Fm {
+    fn update_volume(&mut self, ctx: &AudioContext, volume: f32) -> Result<(), JsValue> {
+        self.nodes
+            .volume
+            .gain()
+            .linear_ramp_to_value_at_time(volume, ctx.current_time() + 0.01)?;
+        Ok(())
+    }
+    fn params(&self) -> super::SoundParams {
+        super::SoundParams::Fm(self.params)
+    }
+    fn update(&mut self, params: super::SoundParams) {
+        if let super::SoundParams::Fm(fm_params) = params {
+            self.params = fm_params;
+        }
+    }
+    fn play(
+        &self,
+        ctx: &AudioContext,
+        fm_params: Option<super::SoundParams>,
+        time: f64,
+    ) -> Result<Option<super::Voice>, JsValue> {
+        let params = match fm_params {
+            Some(super::SoundParams::Fm(params)) => params,
+            _ => self.params,
+        };
+        let modulator = ctx.create_oscillator()?;
+        modulator.frequency().set_value(params.modulator_freq());
+        let depth = ctx.create_gain()?;
+        let carrier = ctx.create_oscillator()?;
+        carrier.frequency().set_value(params.freq);
+        let gain = ctx.create_gain()?;
+        modulator.connect_with_audio_node(&depth)?;
+        depth.connect_with_audio_param(&carrier.frequency())?;
+        carrier.connect_with_audio_node(&gain)?;
+        gain.connect_with_audio_node(&self.nodes.volume)?;
+
+        // The frequency deviation is the index times the modulator frequency.
+        depth.gain().set_value(0.0);
+        depth
+            .gain()
+            .set_value_at_time(params.index * params.modulator_freq(), time)?;
+        depth
+            .gain()
+            .set_target_at_time(0.0, time, (params.index_decay / 3.0) as f64)?;
+        gain.gain().set_value(0.0);
+        gain.gain()
+            .set_target_at_time(0.4 * params.volume, time, 0.0005)?;
+        gain.gain()
+            .set_target_at_time(0.0, time + 0.002, (params.decay / 3.0) as f64)?;
+        modulator.start()?;
+        modulator.stop_with_when(time + 4.0)?;
+        carrier.start()?;
+        carrier.stop_with_when(time + 4.0)?;
+        Ok(Some(super::Voice {
+            gain,
+            sources: vec![modulator.into(), carrier.into()],
+            start: time,
+            end: time + 4.0,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modulator_follows_the_ratio() {
+        let fm = FmParams {
+            freq: 100.0,
+            ratio: 3.5,
+            ..FmParams::default()
+        };
+        assert_eq!(fm.modulator_freq(), 350.0);
+    }
+}
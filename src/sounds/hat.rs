@@ -1,14 +1,16 @@
+use super::{lerp, ParamRange};
+use crate::error::Error;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
-use web_sys::{AudioBuffer, AudioContext, BiquadFilterType};
+use web_sys::{AudioBuffer, AudioContext, AudioScheduledSourceNode, BiquadFilterType};
 
 pub struct Hat {
     pub nodes: HatNodes,
     pub params: HatParams,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
 pub struct HatParams {
     pub freq: f32,
     pub decay: f32,
@@ -16,15 +18,6 @@ pub struct HatParams {
     pub volume: f32,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct HatValues {
-    pub freq: f32,
-    pub decay: f32,
-    pub punch: f32,
-    pub volume: f32,
-    pub step_type: String,
-}
-
 pub struct HatNodes {
     noise_buffer: AudioBuffer,
     volume: web_sys::GainNode,
@@ -41,16 +34,39 @@ impl Default for HatParams {
     }
 }
 
+const FREQ: ParamRange = ParamRange::new("hat freq", 200.0, 20000.0);
+const DECAY: ParamRange = ParamRange::new("hat decay", 0.001, 4.0);
+const PUNCH: ParamRange = ParamRange::new("hat punch", 0.0, 2.0);
+const VOLUME: ParamRange = ParamRange::new("hat volume", 0.0, 1.0);
+
+impl HatParams {
+    pub fn validate(&self) -> Result<(), Error> {
+        FREQ.check(self.freq)?;
+        DECAY.check(self.decay)?;
+        PUNCH.check(self.punch)?;
+        VOLUME.check(self.volume)
+    }
+
+    pub fn perturb<R: Rng + ?Sized>(&mut self, amount: f32, rng: &mut R) {
+        self.freq = FREQ.perturb(self.freq, amount, rng);
+        self.decay = DECAY.perturb(self.decay, amount, rng);
+        self.punch = PUNCH.perturb(self.punch, amount, rng);
+        self.volume = VOLUME.perturb(self.volume, amount, rng);
+    }
+
+    pub fn lerp(&self, other: &HatParams, amount: f32) -> HatParams {
+        HatParams {
+            freq: lerp(self.freq, other.freq, amount),
+            decay: lerp(self.decay, other.decay, amount),
+            punch: lerp(self.punch, other.punch, amount),
+            volume: lerp(self.volume, other.volume, amount),
+        }
+    }
+}
+
 impl Hat {
     pub fn new(ctx: &AudioContext) -> Result<Self, JsValue> {
-        let sr = ctx.sample_rate();
-        let noise_buffer = ctx.create_buffer(1, sr as u32, sr)?;
-        let noise_output = &mut noise_buffer.get_channel_data(0)?;
-        let mut rng = rand::thread_rng();
-        for i in 0..sr as usize {
-            noise_output[i] = 2.0 * rng.gen::<f32>() - 1.0;
-        }
-        noise_buffer.copy_to_channel(noise_output, 0)?;
+        let noise_buffer = super::noise_buffer(ctx)?;
         let volume = ctx.create_gain()?;
         volume.gain().set_value(0.9);
         volume.connect_with_audio_node(&ctx.destination())?;
@@ -69,6 +85,9 @@ impl super::Sound for Hat {
         self.nodes.volume.gain().linear_ramp_to_value_at_time(volume, ctx.current_time() + 0.01)?;
         Ok(())
     }
+    fn params(&self) -> super::SoundParams {
+        super::SoundParams::Hat(self.params)
+    }
     fn update(&mut self, params: super::SoundParams) {
         if let super::SoundParams::Hat(hat_params) = params {
             self.params = hat_params;
@@ -78,14 +97,12 @@ impl super::Sound for Hat {
         &self,
         ctx: &AudioContext,
         hat_params: Option<super::SoundParams>,
-        time_delta: f64,
-        offset: f64,
-    ) -> Result<(), JsValue> {
+        time: f64,
+    ) -> Result<Option<super::Voice>, JsValue> {
         let params = match hat_params {
             Some(super::SoundParams::Hat(params)) => params,
             _ => self.params,
         };
-        let time = time_delta + offset + 0.05;
         let white_noise = ctx.create_buffer_source()?;
         white_noise.set_buffer(Some(&self.nodes.noise_buffer));
         white_noise.set_loop(true);
@@ -111,8 +128,14 @@ impl super::Sound for Hat {
             .set_target_at_time(0.25 * params.volume, time, 0.0005)?;
         let decay = (params.decay * 0.5) as f64;
         gain.gain().set_target_at_time(0.0, time + decay, decay)?;
-        white_noise.start()?;
-        white_noise.stop_with_when(time + 4.0)?;
-        Ok(())
+        let noise_source: AudioScheduledSourceNode = white_noise.into();
+        noise_source.start()?;
+        noise_source.stop_with_when(time + 4.0)?;
+        Ok(Some(super::Voice {
+            gain,
+            sources: vec![noise_source],
+            start: time,
+            end: time + 4.0,
+        }))
     }
 }
@@ -1,15 +1,39 @@
+use super::wave::Wave;
+use super::{lerp, ParamRange};
+use crate::error::Error;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
-use web_sys::AudioContext;
+use web_sys::{AudioBuffer, AudioContext, AudioScheduledSourceNode, BiquadFilterType};
 
-#[derive(Copy, Clone)]
+const SWEEP_POINTS: usize = 64;
+const CLICK_LENGTH: f64 = 0.02;
+
+/// The pitch starts `pitch` times above `freq` and sweeps down over
+/// `pitch_decay` seconds, exponentially unless `pitch_curve` is set, which
+/// makes it linear at 0 and drop ever faster towards 1. The level rises over
+/// `attack`, holds for `hold` and then decays. Without a `hold` the decay sets
+/// in half of `decay` after the hit, as it did before there was one. `click`
+/// mixes in a short noise transient centred on `click_tone`.
+///
+/// Fields missing from older patterns take their default values.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(default)]
 pub struct KickParams {
     pub freq: f32,
     pub pitch: f32,
-    pub wave: web_sys::OscillatorType,
+    pub wave: Wave,
     pub decay: f32,
     pub punch: f32,
     pub volume: f32,
+    pub pitch_decay: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pitch_curve: Option<f32>,
+    pub attack: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hold: Option<f32>,
+    pub click: f32,
+    pub click_tone: f32,
 }
 
 pub struct Kick {
@@ -18,41 +42,136 @@ pub struct Kick {
 }
 
 struct KickNodes {
+    noise_buffer: AudioBuffer,
     volume: web_sys::GainNode
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct KickValues {
-    pub freq: f32,
-    pub pitch: f32,
-    pub wave: String,
-    pub decay: f32,
-    pub punch: f32,
-    pub volume: f32,
-    pub step_type: String,
-}
-
 impl Default for KickParams {
     fn default() -> KickParams {
         KickParams {
             freq: 40.0,
             pitch: 1.0,
-            wave: web_sys::OscillatorType::Sine,
+            wave: Wave::Sine,
             decay: 0.1,
             punch: -30.0 * 0.0,
             volume: 0.7,
+            pitch_decay: 0.02,
+            pitch_curve: None,
+            attack: 0.001,
+            hold: None,
+            click: 0.0,
+            click_tone: 4000.0,
+        }
+    }
+}
+
+const FREQ: ParamRange = ParamRange::new("kick freq", 20.0, 1000.0);
+const PITCH: ParamRange = ParamRange::new("kick pitch", 0.0, 30.0);
+const DECAY: ParamRange = ParamRange::new("kick decay", 0.001, 4.0);
+const PUNCH: ParamRange = ParamRange::new("kick punch", 0.0, 2.0);
+const VOLUME: ParamRange = ParamRange::new("kick volume", 0.0, 1.0);
+const PITCH_DECAY: ParamRange = ParamRange::new("kick pitch decay", 0.001, 1.0);
+const PITCH_CURVE: ParamRange = ParamRange::new("kick pitch curve", 0.0, 1.0);
+const ATTACK: ParamRange = ParamRange::new("kick attack", 0.0005, 0.1);
+const HOLD: ParamRange = ParamRange::new("kick hold", 0.0, 1.0);
+const CLICK: ParamRange = ParamRange::new("kick click", 0.0, 1.0);
+const CLICK_TONE: ParamRange = ParamRange::new("kick click tone", 500.0, 12000.0);
+
+impl KickParams {
+    pub fn validate(&self) -> Result<(), Error> {
+        FREQ.check(self.freq)?;
+        PITCH.check(self.pitch)?;
+        self.wave.validate()?;
+        DECAY.check(self.decay)?;
+        PUNCH.check(self.punch)?;
+        VOLUME.check(self.volume)?;
+        PITCH_DECAY.check(self.pitch_decay)?;
+        if let Some(pitch_curve) = self.pitch_curve {
+            PITCH_CURVE.check(pitch_curve)?;
+        }
+        ATTACK.check(self.attack)?;
+        if let Some(hold) = self.hold {
+            HOLD.check(hold)?;
+        }
+        CLICK.check(self.click)?;
+        CLICK_TONE.check(self.click_tone)
+    }
+
+    pub fn perturb<R: Rng + ?Sized>(&mut self, amount: f32, rng: &mut R) {
+        self.freq = FREQ.perturb(self.freq, amount, rng);
+        self.pitch = PITCH.perturb(self.pitch, amount, rng);
+        self.decay = DECAY.perturb(self.decay, amount, rng);
+        self.punch = PUNCH.perturb(self.punch, amount, rng);
+        self.volume = VOLUME.perturb(self.volume, amount, rng);
+        self.pitch_decay = PITCH_DECAY.perturb(self.pitch_decay, amount, rng);
+        self.pitch_curve = self
+            .pitch_curve
+            .map(|pitch_curve| PITCH_CURVE.perturb(pitch_curve, amount, rng));
+        self.attack = ATTACK.perturb(self.attack, amount, rng);
+        self.hold = self.hold.map(|hold| HOLD.perturb(hold, amount, rng));
+        self.click = CLICK.perturb(self.click, amount, rng);
+        self.click_tone = CLICK_TONE.perturb(self.click_tone, amount, rng);
+    }
+
+    pub fn lerp(&self, other: &KickParams, amount: f32) -> KickParams {
+        KickParams {
+            freq: lerp(self.freq, other.freq, amount),
+            pitch: lerp(self.pitch, other.pitch, amount),
+            wave: if amount < 0.5 { self.wave } else { other.wave },
+            decay: lerp(self.decay, other.decay, amount),
+            punch: lerp(self.punch, other.punch, amount),
+            volume: lerp(self.volume, other.volume, amount),
+            pitch_decay: lerp(self.pitch_decay, other.pitch_decay, amount),
+            pitch_curve: match (self.pitch_curve, other.pitch_curve) {
+                (Some(a), Some(b)) => Some(lerp(a, b, amount)),
+                _ if amount < 0.5 => self.pitch_curve,
+                _ => other.pitch_curve,
+            },
+            attack: lerp(self.attack, other.attack, amount),
+            hold: match (self.hold, other.hold) {
+                (None, None) => None,
+                _ => Some(lerp(self.hold_time(), other.hold_time(), amount)),
+            },
+            click: lerp(self.click, other.click, amount),
+            click_tone: lerp(self.click_tone, other.click_tone, amount),
         }
     }
+
+    /// Seconds between the end of the attack and the start of the decay.
+    pub fn hold_time(&self) -> f32 {
+        self.hold
+            .unwrap_or_else(|| (self.decay * 0.5 - self.attack).max(0.0))
+    }
+
+    /// The oscillator frequency over the pitch sweep, `points` values from
+    /// the start of the sweep to `freq`.
+    pub fn pitch_sweep(&self, points: usize) -> Vec<f32> {
+        let start = self.freq + self.freq * self.pitch;
+        (0..points)
+            .map(|i| {
+                let x = i as f32 / (points - 1) as f32;
+                match self.pitch_curve {
+                    Some(pitch_curve) => {
+                        let exponent = 16f32.powf(pitch_curve);
+                        self.freq + (start - self.freq) * (1.0 - x).powf(exponent)
+                    }
+                    None => start * (self.freq / start).powf(x),
+                }
+            })
+            .collect()
+    }
 }
 
 impl Kick {
     pub fn new(ctx: &AudioContext) -> Result<Self, JsValue> {
+        let noise_buffer = super::noise_buffer(ctx)?;
         let volume = ctx.create_gain()?;
         volume.gain().set_value(0.9);
         volume.connect_with_audio_node(&ctx.destination())?;
         Ok(Kick {
             params: KickParams::default(),
             nodes: KickNodes {
+                noise_buffer,
                 volume
             },
         })
@@ -64,6 +183,9 @@ impl super::Sound for Kick {
         self.nodes.volume.gain().linear_ramp_to_value_at_time(volume, ctx.current_time() + 0.01)?;
         Ok(())
     }
+    fn params(&self) -> super::SoundParams {
+        super::SoundParams::Kick(self.params)
+    }
     fn update(&mut self, params: super::SoundParams) {
         if let super::SoundParams::Kick(kick_params) = params {
             self.params = kick_params
@@ -73,16 +195,14 @@ impl super::Sound for Kick {
         &self,
         ctx: &AudioContext,
         kick_params: Option<super::SoundParams>,
-        time_delta: f64,
-        offset: f64,
-    ) -> Result<(), JsValue> {
+        time: f64,
+    ) -> Result<Option<super::Voice>, JsValue> {
         let params = match kick_params {
             Some(super::SoundParams::Kick(params)) => params,
             _ => self.params,
         };
-        let time = time_delta + offset + 0.05;
         let osc = ctx.create_oscillator()?;
-        osc.set_type(params.wave);
+        params.wave.apply(ctx, &osc)?;
         let gain = ctx.create_gain()?;
         let compressor = ctx.create_dynamics_compressor()?;
         compressor.knee().set_value(1.0);
@@ -94,17 +214,94 @@ impl super::Sound for Kick {
 
         osc.connect_with_audio_node(&gain)?;
         gain.connect_with_audio_node(&compressor)?;
-        osc.frequency()
-            .set_value_at_time(params.freq + params.freq * params.pitch, time)?;
-        osc.frequency()
-            .exponential_ramp_to_value_at_time(params.freq, time + 0.02)?;
+        osc.frequency().set_value_curve_at_time(
+            &mut params.pitch_sweep(SWEEP_POINTS),
+            time,
+            params.pitch_decay as f64,
+        )?;
+        let peak = 0.25 * params.volume;
+        let attack_end = time + params.attack as f64;
         gain.gain().set_value(0.0);
-        gain.gain()
-            .set_target_at_time(0.25 * params.volume, time, 0.0005)?;
+        gain.gain().set_value_at_time(0.0, time)?;
+        gain.gain().linear_ramp_to_value_at_time(peak, attack_end)?;
         let decay = (params.decay * 0.5) as f64;
-        gain.gain().set_target_at_time(0.0, time + decay, decay)?;
+        gain.gain()
+            .set_target_at_time(0.0, attack_end + params.hold_time() as f64, decay)?;
         osc.start()?;
         osc.stop_with_when(time + 4.0)?;
-        Ok(())
+        let mut sources: Vec<AudioScheduledSourceNode> = vec![osc.into()];
+
+        if params.click > 0.0 {
+            let white_noise = ctx.create_buffer_source()?;
+            white_noise.set_buffer(Some(&self.nodes.noise_buffer));
+            white_noise.set_loop(true);
+            let filter = ctx.create_biquad_filter()?;
+            filter.set_type(BiquadFilterType::Bandpass);
+            filter.frequency().set_value(params.click_tone);
+            // The transient skips the amp envelope so a slow attack keeps it.
+            let click_gain = ctx.create_gain()?;
+            white_noise.connect_with_audio_node(&filter)?;
+            filter.connect_with_audio_node(&click_gain)?;
+            click_gain.connect_with_audio_node(&compressor)?;
+            click_gain
+                .gain()
+                .set_value_at_time(params.click * params.volume, time)?;
+            click_gain
+                .gain()
+                .set_target_at_time(0.0, time, CLICK_LENGTH / 5.0)?;
+            let noise_source: AudioScheduledSourceNode = white_noise.into();
+            noise_source.start_with_when(time)?;
+            noise_source.stop_with_when(time + CLICK_LENGTH)?;
+            sources.push(noise_source);
+        }
+        Ok(Some(super::Voice {
+            gain,
+            sources,
+            start: time,
+            end: time + 4.0,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pitch_sweeps_down_to_the_base_frequency() {
+        let linear = KickParams {
+            freq: 50.0,
+            pitch: 2.0,
+            pitch_curve: Some(0.0),
+            ..KickParams::default()
+        };
+        assert_eq!(linear.pitch_sweep(5), vec![150.0, 125.0, 100.0, 75.0, 50.0]);
+        let steep = KickParams {
+            pitch_curve: Some(1.0),
+            ..linear
+        };
+        let sweep = steep.pitch_sweep(5);
+        assert_eq!((sweep[0], sweep[4]), (150.0, 50.0));
+        assert!(sweep[1] < 60.0);
+    }
+
+    #[test]
+    fn defaults_keep_the_original_envelope() {
+        // The original kick ramped exponentially from twice `freq` and
+        // started decaying half of `decay` after the hit.
+        let kick = KickParams {
+            decay: 1.0,
+            ..KickParams::default()
+        };
+        let sweep = kick.pitch_sweep(3);
+        assert_eq!((sweep[0], sweep[2]), (80.0, 40.0));
+        assert!((sweep[1] - 80.0 * 0.5f32.sqrt()).abs() < 1e-4);
+        assert_eq!(kick.pitch_decay, 0.02);
+        assert_eq!(kick.attack + kick.hold_time(), 0.5);
+        let held = KickParams {
+            hold: Some(0.1),
+            ..kick
+        };
+        assert_eq!(held.hold_time(), 0.1);
     }
 }
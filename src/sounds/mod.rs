@@ -1,26 +1,206 @@
-use web_sys::AudioContext;
+use crate::error::{check_range, Error};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use web_sys::{AudioBuffer, AudioContext, AudioScheduledSourceNode, GainNode};
 use wasm_bindgen::prelude::*;
 
 pub mod kick;
 pub mod snare;
 pub mod hat;
+pub mod clap;
+pub mod tom;
+pub mod cymbal;
+pub mod cowbell;
+pub mod rimshot;
+pub mod sampler;
+pub mod fm;
+pub mod wave;
+pub mod wav;
 
 pub trait Sound {
-  fn play(&self, ctx: &AudioContext, params: Option<super::SoundParams>, time_delta: f64, offset: f64) -> Result<(), JsValue>;
+  /// Schedules a hit at `time`, or returns `None` if there is nothing to play.
+  fn play(&self, ctx: &AudioContext, params: Option<super::SoundParams>, time: f64) -> Result<Option<Voice>, JsValue>;
+  fn params(&self) -> SoundParams;
   fn update(&mut self, params: SoundParams);
   fn update_volume(&mut self, ctx: &AudioContext, volume: f32)-> Result<(), JsValue>;
+  /// Gives the sound a decoded sample to play. Synthesized sounds ignore it.
+  fn load_sample(&mut self, _ctx: &AudioContext, _wav: &wav::Wav) -> Result<(), JsValue> {
+    Ok(())
+  }
+}
+
+const CHOKE_TIME: f64 = 0.005;
+
+/// A scheduled hit, kept around so its tail can be cut short.
+pub struct Voice {
+  pub gain: GainNode,
+  pub sources: Vec<AudioScheduledSourceNode>,
+  pub start: f64,
+  pub end: f64,
+}
+
+impl Voice {
+  pub fn is_ringing(&self, time: f64) -> bool {
+    self.start < time && time < self.end
+  }
+
+  /// Fades the voice out quickly from `time` and stops its sources.
+  pub fn choke(&mut self, time: f64) -> Result<(), JsValue> {
+    let gain = self.gain.gain();
+    gain.cancel_scheduled_values(time)?;
+    gain.set_target_at_time(0.0, time, CHOKE_TIME)?;
+    self.end = time + CHOKE_TIME * 10.0;
+    for source in self.sources.iter() {
+      source.stop_with_when(self.end)?;
+    }
+    Ok(())
+  }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(tag = "sound", rename_all = "snake_case")]
 pub enum SoundParams {
   Kick(kick::KickParams),
+  #[serde(deserialize_with = "snare::deserialize_lock")]
   Snare(snare::SnareParams),
   Hat(hat::HatParams),
+  Clap(clap::ClapParams),
+  Tom(tom::TomParams),
+  Cymbal(cymbal::CymbalParams),
+  Cowbell(cowbell::CowbellParams),
+  Rimshot(rimshot::RimshotParams),
+  Sampler(sampler::SamplerParams),
+  Fm(fm::FmParams),
 }
 
-pub fn wave_string_to_osc(wave: &str) -> web_sys::OscillatorType {
-  match wave {
-      "triangle" => web_sys::OscillatorType::Triangle,
-      _ => web_sys::OscillatorType::Sine,
+impl SoundParams {
+  pub fn name(&self) -> &'static str {
+    match self {
+      SoundParams::Kick(_) => "kick",
+      SoundParams::Snare(_) => "snare",
+      SoundParams::Hat(_) => "hat",
+      SoundParams::Clap(_) => "clap",
+      SoundParams::Tom(_) => "tom",
+      SoundParams::Cymbal(_) => "cymbal",
+      SoundParams::Cowbell(_) => "cowbell",
+      SoundParams::Rimshot(_) => "rimshot",
+      SoundParams::Sampler(_) => "sampler",
+      SoundParams::Fm(_) => "fm",
+    }
+  }
+
+  pub fn validate(&self) -> Result<(), Error> {
+    match self {
+      SoundParams::Kick(params) => params.validate(),
+      SoundParams::Snare(params) => params.validate(),
+      SoundParams::Hat(params) => params.validate(),
+      SoundParams::Clap(params) => params.validate(),
+      SoundParams::Tom(params) => params.validate(),
+      SoundParams::Cymbal(params) => params.validate(),
+      SoundParams::Cowbell(params) => params.validate(),
+      SoundParams::Rimshot(params) => params.validate(),
+      SoundParams::Sampler(params) => params.validate(),
+      SoundParams::Fm(params) => params.validate(),
+    }
+  }
+
+  pub fn perturb<R: Rng + ?Sized>(&mut self, amount: f32, rng: &mut R) {
+    match self {
+      SoundParams::Kick(params) => params.perturb(amount, rng),
+      SoundParams::Snare(params) => params.perturb(amount, rng),
+      SoundParams::Hat(params) => params.perturb(amount, rng),
+      SoundParams::Clap(params) => params.perturb(amount, rng),
+      SoundParams::Tom(params) => params.perturb(amount, rng),
+      SoundParams::Cymbal(params) => params.perturb(amount, rng),
+      SoundParams::Cowbell(params) => params.perturb(amount, rng),
+      SoundParams::Rimshot(params) => params.perturb(amount, rng),
+      SoundParams::Sampler(params) => params.perturb(amount, rng),
+      SoundParams::Fm(params) => params.perturb(amount, rng),
+    }
+  }
+
+  pub fn scale_volume(&mut self, factor: f32) {
+    let volume = match self {
+      SoundParams::Kick(params) => &mut params.volume,
+      SoundParams::Snare(params) => &mut params.volume,
+      SoundParams::Hat(params) => &mut params.volume,
+      SoundParams::Clap(params) => &mut params.volume,
+      SoundParams::Tom(params) => &mut params.volume,
+      SoundParams::Cymbal(params) => &mut params.volume,
+      SoundParams::Cowbell(params) => &mut params.volume,
+      SoundParams::Rimshot(params) => &mut params.volume,
+      SoundParams::Sampler(params) => &mut params.volume,
+      SoundParams::Fm(params) => &mut params.volume,
+    };
+    *volume = (*volume * factor).clamp(0.0, 1.0);
+  }
+
+  /// The oscillator shape of pitched sounds.
+  pub fn wave_mut(&mut self) -> Option<&mut wave::Wave> {
+    match self {
+      SoundParams::Kick(params) => Some(&mut params.wave),
+      SoundParams::Tom(params) => Some(&mut params.wave),
+      _ => None,
+    }
+  }
+
+  /// Interpolates towards `other`, or returns `None` for different sounds.
+  pub fn lerp(&self, other: &SoundParams, amount: f32) -> Option<SoundParams> {
+    match (self, other) {
+      (SoundParams::Kick(a), SoundParams::Kick(b)) => Some(SoundParams::Kick(a.lerp(b, amount))),
+      (SoundParams::Snare(a), SoundParams::Snare(b)) => Some(SoundParams::Snare(a.lerp(b, amount))),
+      (SoundParams::Hat(a), SoundParams::Hat(b)) => Some(SoundParams::Hat(a.lerp(b, amount))),
+      (SoundParams::Clap(a), SoundParams::Clap(b)) => Some(SoundParams::Clap(a.lerp(b, amount))),
+      (SoundParams::Tom(a), SoundParams::Tom(b)) => Some(SoundParams::Tom(a.lerp(b, amount))),
+      (SoundParams::Cymbal(a), SoundParams::Cymbal(b)) => Some(SoundParams::Cymbal(a.lerp(b, amount))),
+      (SoundParams::Cowbell(a), SoundParams::Cowbell(b)) => Some(SoundParams::Cowbell(a.lerp(b, amount))),
+      (SoundParams::Rimshot(a), SoundParams::Rimshot(b)) => Some(SoundParams::Rimshot(a.lerp(b, amount))),
+      (SoundParams::Sampler(a), SoundParams::Sampler(b)) => Some(SoundParams::Sampler(a.lerp(b, amount))),
+      (SoundParams::Fm(a), SoundParams::Fm(b)) => Some(SoundParams::Fm(a.lerp(b, amount))),
+      _ => None,
+    }
+  }
+}
+
+/// One second of white noise for voices to loop.
+pub fn noise_buffer(ctx: &AudioContext) -> Result<AudioBuffer, JsValue> {
+  let sr = ctx.sample_rate();
+  let noise_buffer = ctx.create_buffer(1, sr as u32, sr)?;
+  let noise_output = &mut noise_buffer.get_channel_data(0)?;
+  let mut rng = rand::thread_rng();
+  for sample in noise_output.iter_mut() {
+    *sample = 2.0 * rng.gen::<f32>() - 1.0;
+  }
+  noise_buffer.copy_to_channel(noise_output, 0)?;
+  Ok(noise_buffer)
+}
+
+pub fn lerp(a: f32, b: f32, amount: f32) -> f32 {
+  a + (b - a) * amount
+}
+
+pub struct ParamRange {
+  pub name: &'static str,
+  pub min: f32,
+  pub max: f32,
+}
+
+impl ParamRange {
+  pub const fn new(name: &'static str, min: f32, max: f32) -> ParamRange {
+    ParamRange { name, min, max }
+  }
+
+  pub fn check(&self, value: f32) -> Result<(), Error> {
+    check_range(self.name, value.into(), self.min.into(), self.max.into())
+  }
+
+  pub fn clamp(&self, value: f32) -> f32 {
+    value.max(self.min).min(self.max)
+  }
+
+  /// Moves `value` by up to `amount` of the full range in either direction.
+  pub fn perturb<R: Rng + ?Sized>(&self, value: f32, amount: f32, rng: &mut R) -> f32 {
+    let spread = (self.max - self.min) * amount;
+    self.clamp(value + spread * rng.gen_range(-1.0..=1.0))
   }
 }
@@ -0,0 +1,153 @@
+use super::{lerp, ParamRange};
+use crate::error::Error;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use web_sys::{AudioBuffer, AudioContext, AudioScheduledSourceNode, BiquadFilterType};
+
+/// A click ringing through a resonant bandpass at `tone`, with `resonance` as
+/// the filter Q, plus `noise` for the stick.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct RimshotParams {
+    pub tone: f32,
+    pub resonance: f32,
+    pub noise: f32,
+    pub decay: f32,
+    pub volume: f32,
+}
+
+pub struct Rimshot {
+    nodes: RimshotNodes,
+    pub params: RimshotParams,
+}
+
+struct RimshotNodes {
+    noise_buffer: AudioBuffer,
+    volume: web_sys::GainNode,
+}
+
+impl Default for RimshotParams {
+    fn default() -> RimshotParams {
+        RimshotParams {
+            tone: 1700.0,
+            resonance: 8.0,
+            noise: 0.3,
+            decay: 0.03,
+            volume: 0.7,
+        }
+    }
+}
+
+const TONE: ParamRange = ParamRange::new("rimshot tone", 300.0, 5000.0);
+const RESONANCE: ParamRange = ParamRange::new("rimshot resonance", 0.5, 30.0);
+const NOISE: ParamRange = ParamRange::new("rimshot noise", 0.0, 1.0);
+const DECAY: ParamRange = ParamRange::new("rimshot decay", 0.005, 0.5);
+const VOLUME: ParamRange = ParamRange::new("rimshot volume", 0.0, 1.0);
+
+impl RimshotParams {
+    pub fn validate(&self) -> Result<(), Error> {
+        TONE.check(self.tone)?;
+        RESONANCE.check(self.resonance)?;
+        NOISE.check(self.noise)?;
+        DECAY.check(self.decay)?;
+        VOLUME.check(self.volume)
+    }
+
+    pub fn perturb<R: Rng + ?Sized>(&mut self, amount: f32, rng: &mut R) {
+        self.tone = TONE.perturb(self.tone, amount, rng);
+        self.resonance = RESONANCE.perturb(self.resonance, amount, rng);
+        self.noise = NOISE.perturb(self.noise, amount, rng);
+        self.decay = DECAY.perturb(self.decay, amount, rng);
+        self.volume = VOLUME.perturb(self.volume, amount, rng);
+    }
+
+    pub fn lerp(&self, other: &RimshotParams, amount: f32) -> RimshotParams {
+        RimshotParams {
+            tone: lerp(self.tone, other.tone, amount),
+            resonance: lerp(self.resonance, other.resonance, amount),
+            noise: lerp(self.noise, other.noise, amount),
+            decay: lerp(self.decay, other.decay, amount),
+            volume: lerp(self.volume, other.volume, amount),
+        }
+    }
+}
+
+impl Rimshot {
+    pub fn new(ctx: &AudioContext) -> Result<Self, JsValue> {
+        let noise_buffer = super::noise_buffer(ctx)?;
+        let volume = ctx.create_gain()?;
+        volume.gain().set_value(0.9);
+        volume.connect_with_audio_node(&ctx.destination())?;
+        Ok(Rimshot {
+            params: RimshotParams::default(),
+            nodes: RimshotNodes {
+                noise_buffer,
+                volume,
+            },
+        })
+    }
+}
+
+impl super::Sound for Rimshot {
+    fn update_volume(&mut self, ctx: &AudioContext, volume: f32) -> Result<(), JsValue> {
+        self.nodes
+            .volume
+            .gain()
+            .linear_ramp_to_value_at_time(volume, ctx.current_time() + 0.01)?;
+        Ok(())
+    }
+    fn params(&self) -> super::SoundParams {
+        super::SoundParams::Rimshot(self.params)
+    }
+    fn update(&mut self, params: super::SoundParams) {
+        if let super::SoundParams::Rimshot(rimshot_params) = params {
+            self.params = rimshot_params;
+        }
+    }
+    fn play(
+        &self,
+        ctx: &AudioContext,
+        rimshot_params: Option<super::SoundParams>,
+        time: f64,
+    ) -> Result<Option<super::Voice>, JsValue> {
+        let params = match rimshot_params {
+            Some(super::SoundParams::Rimshot(params)) => params,
+            _ => self.params,
+        };
+        let osc = ctx.create_oscillator()?;
+        osc.set_type(web_sys::OscillatorType::Triangle);
+        osc.frequency().set_value(params.tone);
+        let white_noise = ctx.create_buffer_source()?;
+        white_noise.set_buffer(Some(&self.nodes.noise_buffer));
+        white_noise.set_loop(true);
+        let noise_gain = ctx.create_gain()?;
+        noise_gain.gain().set_value(params.noise);
+
+        let filter = ctx.create_biquad_filter()?;
+        filter.set_type(BiquadFilterType::Bandpass);
+        filter.frequency().set_value(params.tone);
+        filter.q().set_value(params.resonance);
+        let gain = ctx.create_gain()?;
+        osc.connect_with_audio_node(&filter)?;
+        white_noise.connect_with_audio_node(&noise_gain)?;
+        noise_gain.connect_with_audio_node(&filter)?;
+        filter.connect_with_audio_node(&gain)?;
+        gain.connect_with_audio_node(&self.nodes.volume)?;
+
+        gain.gain().set_value(0.0);
+        gain.gain().set_value_at_time(0.6 * params.volume, time)?;
+        gain.gain()
+            .set_target_at_time(0.0, time, (params.decay / 3.0) as f64)?;
+        osc.start()?;
+        osc.stop_with_when(time + 4.0)?;
+        let noise_source: AudioScheduledSourceNode = white_noise.into();
+        noise_source.start()?;
+        noise_source.stop_with_when(time + 4.0)?;
+        Ok(Some(super::Voice {
+            gain,
+            sources: vec![osc.into(), noise_source],
+            start: time,
+            end: time + 4.0,
+        }))
+    }
+}
@@ -0,0 +1,181 @@
+use super::wav::Wav;
+use super::{lerp, ParamRange};
+use crate::error::Error;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use web_sys::{AudioBuffer, AudioContext, AudioScheduledSourceNode};
+
+/// Plays a loaded sample. `start` is how far into the sample playback begins,
+/// as a fraction of its length in the direction it plays, and `rate` scales
+/// both speed and pitch.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct SamplerParams {
+    pub start: f32,
+    pub rate: f32,
+    pub reverse: bool,
+    pub decay: f32,
+    pub volume: f32,
+}
+
+pub struct Sampler {
+    nodes: SamplerNodes,
+    pub params: SamplerParams,
+}
+
+struct SamplerNodes {
+    /// The sample and a reversed copy, once one is loaded.
+    buffers: Option<(AudioBuffer, AudioBuffer)>,
+    volume: web_sys::GainNode,
+}
+
+impl Default for SamplerParams {
+    fn default() -> SamplerParams {
+        SamplerParams {
+            start: 0.0,
+            rate: 1.0,
+            reverse: false,
+            decay: 1.0,
+            volume: 0.7,
+        }
+    }
+}
+
+const START: ParamRange = ParamRange::new("sampler start", 0.0, 1.0);
+const RATE: ParamRange = ParamRange::new("sampler rate", 0.25, 4.0);
+const DECAY: ParamRange = ParamRange::new("sampler decay", 0.01, 10.0);
+const VOLUME: ParamRange = ParamRange::new("sampler volume", 0.0, 1.0);
+
+impl SamplerParams {
+    pub fn validate(&self) -> Result<(), Error> {
+        START.check(self.start)?;
+        RATE.check(self.rate)?;
+        DECAY.check(self.decay)?;
+        VOLUME.check(self.volume)
+    }
+
+    pub fn perturb<R: Rng + ?Sized>(&mut self, amount: f32, rng: &mut R) {
+        self.start = START.perturb(self.start, amount, rng);
+        self.rate = RATE.perturb(self.rate, amount, rng);
+        self.decay = DECAY.perturb(self.decay, amount, rng);
+        self.volume = VOLUME.perturb(self.volume, amount, rng);
+    }
+
+    pub fn lerp(&self, other: &SamplerParams, amount: f32) -> SamplerParams {
+        SamplerParams {
+            start: lerp(self.start, other.start, amount),
+            rate: lerp(self.rate, other.rate, amount),
+            reverse: if amount < 0.5 {
+                self.reverse
+            } else {
+                other.reverse
+            },
+            decay: lerp(self.decay, other.decay, amount),
+            volume: lerp(self.volume, other.volume, amount),
+        }
+    }
+}
+
+impl Sampler {
+    pub fn new(ctx: &AudioContext) -> Result<Self, JsValue> {
+        let volume = ctx.create_gain()?;
+        volume.gain().set_value(0.9);
+        volume.connect_with_audio_node(&ctx.destination())?;
+        Ok(Sampler {
+            params: SamplerParams::default(),
+            nodes: SamplerNodes {
+                buffers: None,
+                volume,
+            },
+        })
+    }
+}
+
+fn create_buffer<'a>(
+    ctx: &AudioContext,
+    wav: &Wav,
+    channels: impl Iterator<Item = &'a [f32]>,
+) -> Result<AudioBuffer, JsValue> {
+    let buffer = ctx.create_buffer(
+        wav.channels.len() as u32,
+        wav.len() as u32,
+        wav.sample_rate as f32,
+    )?;
+    for (i, samples) in channels.enumerate() {
+        buffer.copy_to_channel(samples, i as i32)?;
+    }
+    Ok(buffer)
+}
+
+impl super::Sound for Sampler {
+    fn update_volume(&mut self, ctx: &AudioContext, volume: f32) -> Result<(), JsValue> {
+        self.nodes
+            .volume
+            .gain()
+            .linear_ramp_to_value_at_time(volume, ctx.current_time() + 0.01)?;
+        Ok(())
+    }
+    fn params(&self) -> super::SoundParams {
+        super::SoundParams::Sampler(self.params)
+    }
+    fn update(&mut self, params: super::SoundParams) {
+        if let super::SoundParams::Sampler(sampler_params) = params {
+            self.params = sampler_params;
+        }
+    }
+    fn load_sample(&mut self, ctx: &AudioContext, wav: &Wav) -> Result<(), JsValue> {
+        if wav.is_empty() {
+            self.nodes.buffers = None;
+            return Ok(());
+        }
+        let forward = create_buffer(ctx, wav, wav.channels.iter().map(Vec::as_slice))?;
+        let reversed: Vec<Vec<f32>> = wav
+            .channels
+            .iter()
+            .map(|channel| channel.iter().rev().copied().collect())
+            .collect();
+        let backward = create_buffer(ctx, wav, reversed.iter().map(Vec::as_slice))?;
+        self.nodes.buffers = Some((forward, backward));
+        Ok(())
+    }
+    fn play(
+        &self,
+        ctx: &AudioContext,
+        sampler_params: Option<super::SoundParams>,
+        time: f64,
+    ) -> Result<Option<super::Voice>, JsValue> {
+        let params = match sampler_params {
+            Some(super::SoundParams::Sampler(params)) => params,
+            _ => self.params,
+        };
+        let (forward, backward) = match &self.nodes.buffers {
+            Some(buffers) => buffers,
+            // Nothing to play until a sample is loaded.
+            None => return Ok(None),
+        };
+        let gain = ctx.create_gain()?;
+        gain.connect_with_audio_node(&self.nodes.volume)?;
+        let buffer = if params.reverse { backward } else { forward };
+        let offset = buffer.duration() * params.start as f64;
+        let length = (buffer.duration() - offset) / params.rate as f64;
+
+        let source = ctx.create_buffer_source()?;
+        source.set_buffer(Some(buffer));
+        source.playback_rate().set_value(params.rate);
+        source.connect_with_audio_node(&gain)?;
+
+        gain.gain().set_value(0.0);
+        gain.gain().set_value_at_time(params.volume, time)?;
+        gain.gain()
+            .set_target_at_time(0.0, time, (params.decay / 3.0) as f64)?;
+        source.start_with_when_and_grain_offset(time, offset)?;
+        let source: AudioScheduledSourceNode = source.into();
+        source.stop_with_when(time + length)?;
+        Ok(Some(super::Voice {
+            gain,
+            sources: vec![source],
+            start: time,
+            end: time + length,
+        }))
+    }
+}
@@ -1,18 +1,33 @@
+use super::{lerp, ParamRange};
+use crate::error::Error;
 use rand::Rng;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use wasm_bindgen::prelude::*;
-use web_sys::{AudioBuffer, AudioContext};
+use web_sys::{AudioBuffer, AudioContext, AudioScheduledSourceNode, BiquadFilterType, OscillatorType};
 
 pub struct Snare {
   nodes: SnareNodes,
   pub params: SnareParams,
 }
 
-#[derive(Copy, Clone)]
+/// Two body oscillators at `freq` and `freq * ratio` start `pitch` semitones
+/// high and fall over `pitch_time`, ringing for `decay`. The snappy noise
+/// goes through a highpass at `snappy_tone` and dies away over
+/// `snappy_decay`. `tone` and `snappy` are the levels of the two paths.
+///
+/// Fields missing from older patterns take their default values.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(default)]
 pub struct SnareParams {
   pub freq: f32,
-  pub blend: f32,
+  pub ratio: f32,
+  pub pitch: f32,
+  pub pitch_time: f32,
   pub decay: f32,
+  pub tone: f32,
+  pub snappy: f32,
+  pub snappy_tone: f32,
+  pub snappy_decay: f32,
   pub punch: f32,
   pub volume: f32,
 }
@@ -22,38 +37,112 @@ struct SnareNodes {
   volume: web_sys::GainNode,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct SnareValues {
-  pub freq: f32,
-  pub blend: f32,
-  pub decay: f32,
-  pub punch: f32,
-  pub volume: f32,
-  pub step_type: String,
-}
-
 impl Default for SnareParams {
   fn default() -> SnareParams {
     SnareParams {
-      freq: 120.0,
-      blend: 0.5,
+      freq: 180.0,
+      ratio: 1.8,
+      pitch: 5.0,
+      pitch_time: 0.03,
       decay: 0.1,
+      tone: 0.5,
+      snappy: 0.5,
+      snappy_tone: 1800.0,
+      snappy_decay: 0.2,
       punch: 0.5,
       volume: 0.7,
     }
   }
 }
 
+/// Patterns saved before `tone` and `snappy` carry a single `blend`, the
+/// snappy level, with the body getting the rest.
+#[derive(Deserialize)]
+struct SnareLock {
+  #[serde(flatten)]
+  params: SnareParams,
+  blend: Option<f32>,
+}
+
+pub(crate) fn deserialize_lock<'de, D>(deserializer: D) -> Result<SnareParams, D::Error>
+where
+  D: Deserializer<'de>,
+{
+  let lock = SnareLock::deserialize(deserializer)?;
+  let mut params = lock.params;
+  if let Some(blend) = lock.blend {
+    params.tone = 1.0 - blend;
+    params.snappy = blend;
+  }
+  Ok(params)
+}
+
+const FREQ: ParamRange = ParamRange::new("snare freq", 20.0, 2000.0);
+const RATIO: ParamRange = ParamRange::new("snare ratio", 1.0, 3.0);
+const PITCH: ParamRange = ParamRange::new("snare pitch", 0.0, 24.0);
+const PITCH_TIME: ParamRange = ParamRange::new("snare pitch time", 0.005, 0.5);
+const DECAY: ParamRange = ParamRange::new("snare decay", 0.001, 4.0);
+const TONE: ParamRange = ParamRange::new("snare tone", 0.0, 1.0);
+const SNAPPY: ParamRange = ParamRange::new("snare snappy", 0.0, 1.0);
+const SNAPPY_TONE: ParamRange = ParamRange::new("snare snappy tone", 200.0, 10000.0);
+const SNAPPY_DECAY: ParamRange = ParamRange::new("snare snappy decay", 0.01, 4.0);
+const PUNCH: ParamRange = ParamRange::new("snare punch", 0.0, 2.0);
+const VOLUME: ParamRange = ParamRange::new("snare volume", 0.0, 1.0);
+
+impl SnareParams {
+  pub fn validate(&self) -> Result<(), Error> {
+    FREQ.check(self.freq)?;
+    RATIO.check(self.ratio)?;
+    PITCH.check(self.pitch)?;
+    PITCH_TIME.check(self.pitch_time)?;
+    DECAY.check(self.decay)?;
+    TONE.check(self.tone)?;
+    SNAPPY.check(self.snappy)?;
+    SNAPPY_TONE.check(self.snappy_tone)?;
+    SNAPPY_DECAY.check(self.snappy_decay)?;
+    PUNCH.check(self.punch)?;
+    VOLUME.check(self.volume)
+  }
+
+  pub fn perturb<R: Rng + ?Sized>(&mut self, amount: f32, rng: &mut R) {
+    self.freq = FREQ.perturb(self.freq, amount, rng);
+    self.ratio = RATIO.perturb(self.ratio, amount, rng);
+    self.pitch = PITCH.perturb(self.pitch, amount, rng);
+    self.pitch_time = PITCH_TIME.perturb(self.pitch_time, amount, rng);
+    self.decay = DECAY.perturb(self.decay, amount, rng);
+    self.tone = TONE.perturb(self.tone, amount, rng);
+    self.snappy = SNAPPY.perturb(self.snappy, amount, rng);
+    self.snappy_tone = SNAPPY_TONE.perturb(self.snappy_tone, amount, rng);
+    self.snappy_decay = SNAPPY_DECAY.perturb(self.snappy_decay, amount, rng);
+    self.punch = PUNCH.perturb(self.punch, amount, rng);
+    self.volume = VOLUME.perturb(self.volume, amount, rng);
+  }
+
+  pub fn lerp(&self, other: &SnareParams, amount: f32) -> SnareParams {
+    SnareParams {
+      freq: lerp(self.freq, other.freq, amount),
+      ratio: lerp(self.ratio, other.ratio, amount),
+      pitch: lerp(self.pitch, other.pitch, amount),
+      pitch_time: lerp(self.pitch_time, other.pitch_time, amount),
+      decay: lerp(self.decay, other.decay, amount),
+      tone: lerp(self.tone, other.tone, amount),
+      snappy: lerp(self.snappy, other.snappy, amount),
+      snappy_tone: lerp(self.snappy_tone, other.snappy_tone, amount),
+      snappy_decay: lerp(self.snappy_decay, other.snappy_decay, amount),
+      punch: lerp(self.punch, other.punch, amount),
+      volume: lerp(self.volume, other.volume, amount),
+    }
+  }
+
+  /// The resting frequencies of the two body oscillators.
+  pub fn body_freqs(&self) -> [f32; 2] {
+    [self.freq, self.freq * self.ratio]
+  }
+}
+
 impl Snare {
   pub fn new(ctx: &AudioContext) -> Result<Self, JsValue> {
-    let sr = ctx.sample_rate();
-    let noise_buffer = ctx.create_buffer(1, sr as u32, sr)?;
-    let noise_output = &mut noise_buffer.get_channel_data(0)?;
-    let mut rng = rand::thread_rng();
-    for i in 0..sr as usize {
-      noise_output[i] = 2.0 * rng.gen::<f32>() - 1.0;
-    }
-    noise_buffer.copy_to_channel(noise_output, 0)?;
+    let noise_buffer = super::noise_buffer(ctx)?;
     let volume = ctx.create_gain()?;
     volume.gain().set_value(0.9);
     volume.connect_with_audio_node(&ctx.destination())?;
@@ -76,6 +165,9 @@ impl super::Sound for Snare {
       .linear_ramp_to_value_at_time(volume, ctx.current_time() + 0.01)?;
     Ok(())
   }
+  fn params(&self) -> super::SoundParams {
+    super::SoundParams::Snare(self.params)
+  }
   fn update(&mut self, params: super::SoundParams) {
     if let super::SoundParams::Snare(snare_params) = params {
       self.params = snare_params
@@ -85,25 +177,12 @@ impl super::Sound for Snare {
     &self,
     ctx: &AudioContext,
     snare_params: Option<super::SoundParams>,
-    time_delta: f64,
-    offset: f64,
-  ) -> Result<(), JsValue> {
+    time: f64,
+  ) -> Result<Option<super::Voice>, JsValue> {
     let params = match snare_params {
       Some(super::SoundParams::Snare(params)) => params,
       _ => self.params,
     };
-    let time = time_delta + offset + 0.05;
-    let osc = ctx.create_oscillator()?;
-
-    let white_noise = ctx.create_buffer_source()?;
-    white_noise.set_buffer(Some(&self.nodes.noise_buffer));
-    white_noise.set_loop(true);
-
-    let noise_gain = ctx.create_gain()?;
-    let osc_gain = ctx.create_gain()?;
-    noise_gain.gain().set_value(params.blend);
-    osc_gain.gain().set_value(1.0 - params.blend);
-
     let gain = ctx.create_gain()?;
     let compressor = ctx.create_dynamics_compressor()?;
     compressor.threshold().set_value(-30.0 * params.punch);
@@ -111,27 +190,73 @@ impl super::Sound for Snare {
     compressor.ratio().set_value(5.0);
     compressor.attack().set_value(0.1);
     compressor.release().set_value(0.1);
-
-    osc.connect_with_audio_node(&osc_gain)?;
-    white_noise.connect_with_audio_node(&noise_gain)?;
-    osc_gain.connect_with_audio_node(&gain)?;
-    noise_gain.connect_with_audio_node(&gain)?;
     gain.connect_with_audio_node(&compressor)?;
     compressor.connect_with_audio_node(&self.nodes.volume)?;
-
-    osc
-      .frequency()
-      .exponential_ramp_to_value_at_time(params.freq, time)?;
     gain.gain().set_value(0.0);
     gain
       .gain()
       .set_target_at_time(0.25 * params.volume, time, 0.0005)?;
+
+    let body_gain = ctx.create_gain()?;
+    body_gain.connect_with_audio_node(&gain)?;
+    body_gain.gain().set_value(0.0);
+    body_gain.gain().set_value_at_time(params.tone, time)?;
     let decay = (params.decay * 0.5) as f64;
-    gain.gain().set_target_at_time(0.0, time + decay, decay)?;
-    osc.start()?;
-    osc.stop_with_when(time + 4.0)?;
-    white_noise.start()?;
-    white_noise.stop_with_when(time + 4.0)?;
-    Ok(())
+    body_gain.gain().set_target_at_time(0.0, time + decay, decay)?;
+    let pitch_end = time + params.pitch_time as f64;
+    let mut sources: Vec<AudioScheduledSourceNode> = Vec::new();
+    for freq in params.body_freqs().iter() {
+      let osc = ctx.create_oscillator()?;
+      osc.set_type(OscillatorType::Triangle);
+      osc
+        .frequency()
+        .set_value_at_time(freq * 2f32.powf(params.pitch / 12.0), time)?;
+      osc.frequency().exponential_ramp_to_value_at_time(*freq, pitch_end)?;
+      osc.connect_with_audio_node(&body_gain)?;
+      osc.start()?;
+      osc.stop_with_when(time + 4.0)?;
+      sources.push(osc.into());
+    }
+
+    let white_noise = ctx.create_buffer_source()?;
+    white_noise.set_buffer(Some(&self.nodes.noise_buffer));
+    white_noise.set_loop(true);
+    let filter = ctx.create_biquad_filter()?;
+    filter.set_type(BiquadFilterType::Highpass);
+    filter.frequency().set_value(params.snappy_tone);
+    let snappy_gain = ctx.create_gain()?;
+    white_noise.connect_with_audio_node(&filter)?;
+    filter.connect_with_audio_node(&snappy_gain)?;
+    snappy_gain.connect_with_audio_node(&gain)?;
+    snappy_gain.gain().set_value(0.0);
+    snappy_gain.gain().set_value_at_time(params.snappy, time)?;
+    snappy_gain
+      .gain()
+      .set_target_at_time(0.0, time, (params.snappy_decay / 3.0) as f64)?;
+    let noise_source: AudioScheduledSourceNode = white_noise.into();
+    noise_source.start()?;
+    noise_source.stop_with_when(time + 4.0)?;
+    sources.push(noise_source);
+    Ok(Some(super::Voice {
+      gain,
+      sources,
+      start: time,
+      end: time + 4.0,
+    }))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn second_body_follows_the_ratio() {
+    let snare = SnareParams {
+      freq: 200.0,
+      ratio: 1.5,
+      ..SnareParams::default()
+    };
+    assert_eq!(snare.body_freqs(), [200.0, 300.0]);
   }
 }
@@ -0,0 +1,215 @@
+use super::wave::Wave;
+use super::{lerp, ParamRange};
+use crate::error::Error;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use web_sys::{AudioBuffer, AudioContext, AudioScheduledSourceNode, BiquadFilterType};
+
+const NOISE_ATTACK: f64 = 0.001;
+const NOISE_LENGTH: f64 = 0.02;
+
+/// `note` is a MIDI note number and `tune` a detune in cents, so toms can be
+/// tuned to the song. The pitch starts `pitch_amount` semitones higher and
+/// falls to the note over `pitch_time` seconds; `noise` adds a short stick
+/// attack.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct TomParams {
+    pub note: f32,
+    pub tune: f32,
+    pub wave: Wave,
+    pub pitch_amount: f32,
+    pub pitch_time: f32,
+    pub decay: f32,
+    pub noise: f32,
+    pub volume: f32,
+}
+
+pub struct Tom {
+    nodes: TomNodes,
+    pub params: TomParams,
+}
+
+struct TomNodes {
+    noise_buffer: AudioBuffer,
+    volume: web_sys::GainNode,
+}
+
+impl Default for TomParams {
+    fn default() -> TomParams {
+        TomParams {
+            note: 45.0,
+            tune: 0.0,
+            wave: Wave::Sine,
+            pitch_amount: 7.0,
+            pitch_time: 0.08,
+            decay: 0.4,
+            noise: 0.2,
+            volume: 0.7,
+        }
+    }
+}
+
+const NOTE: ParamRange = ParamRange::new("tom note", 24.0, 84.0);
+const TUNE: ParamRange = ParamRange::new("tom tune", -100.0, 100.0);
+const PITCH_AMOUNT: ParamRange = ParamRange::new("tom pitch amount", 0.0, 24.0);
+const PITCH_TIME: ParamRange = ParamRange::new("tom pitch time", 0.005, 1.0);
+const DECAY: ParamRange = ParamRange::new("tom decay", 0.01, 4.0);
+const NOISE: ParamRange = ParamRange::new("tom noise", 0.0, 1.0);
+const VOLUME: ParamRange = ParamRange::new("tom volume", 0.0, 1.0);
+
+impl TomParams {
+    pub fn validate(&self) -> Result<(), Error> {
+        NOTE.check(self.note)?;
+        self.wave.validate()?;
+        TUNE.check(self.tune)?;
+        PITCH_AMOUNT.check(self.pitch_amount)?;
+        PITCH_TIME.check(self.pitch_time)?;
+        DECAY.check(self.decay)?;
+        NOISE.check(self.noise)?;
+        VOLUME.check(self.volume)
+    }
+
+    pub fn perturb<R: Rng + ?Sized>(&mut self, amount: f32, rng: &mut R) {
+        self.note = NOTE.perturb(self.note, amount, rng);
+        self.tune = TUNE.perturb(self.tune, amount, rng);
+        self.pitch_amount = PITCH_AMOUNT.perturb(self.pitch_amount, amount, rng);
+        self.pitch_time = PITCH_TIME.perturb(self.pitch_time, amount, rng);
+        self.decay = DECAY.perturb(self.decay, amount, rng);
+        self.noise = NOISE.perturb(self.noise, amount, rng);
+        self.volume = VOLUME.perturb(self.volume, amount, rng);
+    }
+
+    pub fn lerp(&self, other: &TomParams, amount: f32) -> TomParams {
+        TomParams {
+            note: lerp(self.note, other.note, amount),
+            tune: lerp(self.tune, other.tune, amount),
+            wave: if amount < 0.5 { self.wave } else { other.wave },
+            pitch_amount: lerp(self.pitch_amount, other.pitch_amount, amount),
+            pitch_time: lerp(self.pitch_time, other.pitch_time, amount),
+            decay: lerp(self.decay, other.decay, amount),
+            noise: lerp(self.noise, other.noise, amount),
+            volume: lerp(self.volume, other.volume, amount),
+        }
+    }
+
+    pub fn freq(&self) -> f32 {
+        note_to_freq(self.note + self.tune / 100.0)
+    }
+}
+
+pub fn note_to_freq(note: f32) -> f32 {
+    440.0 * 2f32.powf((note - 69.0) / 12.0)
+}
+
+impl Tom {
+    pub fn new(ctx: &AudioContext) -> Result<Self, JsValue> {
+        let noise_buffer = super::noise_buffer(ctx)?;
+        let volume = ctx.create_gain()?;
+        volume.gain().set_value(0.9);
+        volume.connect_with_audio_node(&ctx.destination())?;
+        Ok(Tom {
+            params: TomParams::default(),
+            nodes: TomNodes {
+                noise_buffer,
+                volume,
+            },
+        })
+    }
+}
+
+impl super::Sound for Tom {
+    fn update_volume(&mut self, ctx: &AudioContext, volume: f32) -> Result<(), JsValue> {
+        self.nodes
+            .volume
+            .gain()
+            .linear_ramp_to_value_at_time(volume, ctx.current_time() + 0.01)?;
+        Ok(())
+    }
+    fn params(&self) -> super::SoundParams {
+        super::SoundParams::Tom(self.params)
+    }
+    fn update(&mut self, params: super::SoundParams) {
+        if let super::SoundParams::Tom(tom_params) = params {
+            self.params = tom_params;
+        }
+    }
+    fn play(
+        &self,
+        ctx: &AudioContext,
+        tom_params: Option<super::SoundParams>,
+        time: f64,
+    ) -> Result<Option<super::Voice>, JsValue> {
+        let params = match tom_params {
+            Some(super::SoundParams::Tom(params)) => params,
+            _ => self.params,
+        };
+        let freq = params.freq();
+        let osc = ctx.create_oscillator()?;
+        params.wave.apply(ctx, &osc)?;
+        let gain = ctx.create_gain()?;
+        osc.connect_with_audio_node(&gain)?;
+        gain.connect_with_audio_node(&self.nodes.volume)?;
+
+        osc.frequency()
+            .set_value_at_time(freq * 2f32.powf(params.pitch_amount / 12.0), time)?;
+        osc.frequency()
+            .exponential_ramp_to_value_at_time(freq, time + params.pitch_time as f64)?;
+        gain.gain().set_value(0.0);
+        gain.gain()
+            .set_target_at_time(0.4 * params.volume, time, 0.001)?;
+        gain.gain()
+            .set_target_at_time(0.0, time + 0.005, (params.decay / 3.0) as f64)?;
+        osc.start()?;
+        osc.stop_with_when(time + 4.0)?;
+        let mut sources: Vec<AudioScheduledSourceNode> = vec![osc.into()];
+
+        if params.noise > 0.0 {
+            let white_noise = ctx.create_buffer_source()?;
+            white_noise.set_buffer(Some(&self.nodes.noise_buffer));
+            white_noise.set_loop(true);
+            let filter = ctx.create_biquad_filter()?;
+            filter.set_type(BiquadFilterType::Highpass);
+            filter.frequency().set_value(freq * 4.0);
+            // Ramp the burst in and out so it does not click.
+            let noise_gain = ctx.create_gain()?;
+            noise_gain.gain().set_value(0.0);
+            noise_gain.gain().set_value_at_time(0.0, time)?;
+            noise_gain
+                .gain()
+                .linear_ramp_to_value_at_time(params.noise, time + NOISE_ATTACK)?;
+            noise_gain
+                .gain()
+                .set_target_at_time(0.0, time + NOISE_ATTACK, NOISE_LENGTH / 5.0)?;
+            white_noise.connect_with_audio_node(&filter)?;
+            filter.connect_with_audio_node(&noise_gain)?;
+            noise_gain.connect_with_audio_node(&gain)?;
+            let noise_source: AudioScheduledSourceNode = white_noise.into();
+            noise_source.start_with_when(time)?;
+            noise_source.stop_with_when(time + NOISE_LENGTH)?;
+            sources.push(noise_source);
+        }
+        Ok(Some(super::Voice {
+            gain,
+            sources,
+            start: time,
+            end: time + 4.0,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tunes_by_note_and_cents() {
+        assert_eq!(note_to_freq(69.0), 440.0);
+        let tom = TomParams {
+            note: 56.0,
+            tune: 100.0,
+            ..TomParams::default()
+        };
+        assert!((tom.freq() - 220.0).abs() < 0.01);
+    }
+}
@@ -0,0 +1,197 @@
+use crate::error::Error;
+
+const PCM: u16 = 1;
+const IEEE_FLOAT: u16 = 3;
+const EXTENSIBLE: u16 = 0xFFFE;
+/// Web Audio buffers hold at most this many channels.
+const MAX_CHANNELS: u16 = 32;
+
+/// A decoded WAV file, one `Vec` of samples in -1..1 per channel.
+#[derive(Debug, PartialEq)]
+pub struct Wav {
+    pub sample_rate: u32,
+    pub channels: Vec<Vec<f32>>,
+}
+
+impl Wav {
+    pub fn len(&self) -> usize {
+        self.channels.first().map_or(0, Vec::len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+struct Format {
+    tag: u16,
+    channels: u16,
+    sample_rate: u32,
+    bits: u16,
+}
+
+/// Decodes integer PCM (8, 16, 24 or 32 bit) and float (32 or 64 bit) WAV
+/// files, including the extensible variants. Unknown chunks are skipped.
+pub fn decode(bytes: &[u8]) -> Result<Wav, Error> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(malformed("not a RIFF WAVE file"));
+    }
+    let mut format = None;
+    let mut rest = &bytes[12..];
+    while rest.len() >= 8 {
+        let id = &rest[0..4];
+        let size = u32_at(rest, 4);
+        let body = &rest[8..];
+        // Recorders that stream to disk sometimes leave the size too large.
+        let chunk = &body[..(size as usize).min(body.len())];
+        match id {
+            b"fmt " => format = Some(parse_format(chunk)?),
+            b"data" => {
+                let format = format.ok_or_else(|| malformed("data chunk before fmt chunk"))?;
+                return decode_samples(&format, chunk);
+            }
+            _ => {}
+        }
+        // Chunks are padded to an even length.
+        let padded = size.saturating_add(size % 2) as usize;
+        rest = &body[padded.min(body.len())..];
+    }
+    Err(malformed("no data chunk"))
+}
+
+fn parse_format(chunk: &[u8]) -> Result<Format, Error> {
+    if chunk.len() < 16 {
+        return Err(malformed("fmt chunk too short"));
+    }
+    let mut tag = u16_at(chunk, 0);
+    if tag == EXTENSIBLE {
+        if chunk.len() < 26 {
+            return Err(malformed("extensible fmt chunk too short"));
+        }
+        // The sub-format GUID starts with the actual format tag.
+        tag = u16_at(chunk, 24);
+    }
+    let format = Format {
+        tag,
+        channels: u16_at(chunk, 2),
+        sample_rate: u32_at(chunk, 4),
+        bits: u16_at(chunk, 14),
+    };
+    if format.channels == 0 || format.channels > MAX_CHANNELS {
+        return Err(malformed(&format!("{} channels", format.channels)));
+    }
+    if format.sample_rate == 0 {
+        return Err(malformed("sample rate of 0"));
+    }
+    match (format.tag, format.bits) {
+        (PCM, 8) | (PCM, 16) | (PCM, 24) | (PCM, 32) => Ok(format),
+        (IEEE_FLOAT, 32) | (IEEE_FLOAT, 64) => Ok(format),
+        (tag, bits) => Err(malformed(&format!(
+            "unsupported format {} with {} bits per sample",
+            tag, bits
+        ))),
+    }
+}
+
+fn decode_samples(format: &Format, data: &[u8]) -> Result<Wav, Error> {
+    let width = format.bits as usize / 8;
+    let channel_count = format.channels as usize;
+    let frames = data.len() / (width * channel_count);
+    let mut channels = vec![Vec::with_capacity(frames); channel_count];
+    for frame in data.chunks_exact(width * channel_count) {
+        for (channel, sample) in channels.iter_mut().zip(frame.chunks_exact(width)) {
+            channel.push(sample_value(format.tag, sample));
+        }
+    }
+    Ok(Wav {
+        sample_rate: format.sample_rate,
+        channels,
+    })
+}
+
+fn sample_value(tag: u16, sample: &[u8]) -> f32 {
+    match (tag, sample.len()) {
+        (PCM, 1) => (sample[0] as f32 - 128.0) / 128.0,
+        (PCM, 2) => i16::from_le_bytes([sample[0], sample[1]]) as f32 / 32768.0,
+        // Shift the 24 bits to the top of an i32 to keep the sign.
+        (PCM, 3) => {
+            i32::from_le_bytes([0, sample[0], sample[1], sample[2]]) as f32 / 2_147_483_648.0
+        }
+        (PCM, 4) => {
+            i32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]) as f32
+                / 2_147_483_648.0
+        }
+        (_, 4) => f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]),
+        _ => {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(sample);
+            f64::from_le_bytes(bytes) as f32
+        }
+    }
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+fn malformed(reason: &str) -> Error {
+    Error::MalformedWav(reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wav(tag: u16, channels: u16, bits: u16, data: &[u8]) -> Vec<u8> {
+        let mut bytes = b"RIFF\0\0\0\0WAVE".to_vec();
+        bytes.extend_from_slice(b"LIST\x03\0\0\0abc\0");
+        bytes.extend_from_slice(b"fmt \x10\0\0\0");
+        bytes.extend_from_slice(&tag.to_le_bytes());
+        bytes.extend_from_slice(&channels.to_le_bytes());
+        bytes.extend_from_slice(&44100u32.to_le_bytes());
+        let block = channels * bits / 8;
+        bytes.extend_from_slice(&(44100 * block as u32).to_le_bytes());
+        bytes.extend_from_slice(&block.to_le_bytes());
+        bytes.extend_from_slice(&bits.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn decodes_integer_and_float_samples() {
+        let stereo = decode(&wav(PCM, 2, 16, &[0, 0x80, 0, 0x40, 0xff, 0x7f, 0, 0])).unwrap();
+        assert_eq!(stereo.sample_rate, 44100);
+        assert_eq!(
+            stereo.channels,
+            vec![vec![-1.0, 32767.0 / 32768.0], vec![0.5, 0.0]]
+        );
+
+        let bytes = wav(PCM, 1, 24, &[0, 0, 0xc0, 0, 0, 0x40]);
+        assert_eq!(decode(&bytes).unwrap().channels, vec![vec![-0.5, 0.5]]);
+
+        let bytes = wav(PCM, 1, 8, &[0, 128]);
+        assert_eq!(decode(&bytes).unwrap().channels, vec![vec![-1.0, 0.0]]);
+
+        let bytes = wav(IEEE_FLOAT, 1, 32, &0.25f32.to_le_bytes());
+        assert_eq!(decode(&bytes).unwrap().channels, vec![vec![0.25]]);
+    }
+
+    #[test]
+    fn rejects_what_it_cannot_play() {
+        assert!(decode(b"RIFF\0\0\0\0AVI ").is_err());
+        assert!(decode(&wav(2, 1, 4, &[0, 0])).is_err());
+        assert!(decode(&wav(PCM, 0, 16, &[])).is_err());
+        let mut no_data = wav(PCM, 1, 16, &[]);
+        no_data.truncate(no_data.len() - 8);
+        assert_eq!(decode(&no_data), Err(malformed("no data chunk")));
+        let mut oversized = b"RIFF\0\0\0\0WAVE".to_vec();
+        oversized.extend_from_slice(b"LIST\xff\xff\xff\xffabc");
+        assert_eq!(decode(&oversized), Err(malformed("no data chunk")));
+    }
+}
@@ -0,0 +1,204 @@
+use super::ParamRange;
+use crate::error::{check_range, Error};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+use std::str::FromStr;
+use wasm_bindgen::prelude::*;
+use web_sys::{AudioContext, OscillatorNode, OscillatorType};
+
+/// Number of harmonics in a custom wavetable.
+pub const HARMONICS: usize = 8;
+
+const HARMONIC: ParamRange = ParamRange::new("harmonic", 0.0, 1.0);
+
+/// Points per cycle searched for the peak of a custom wave.
+const PEAK_STEPS: usize = 1024;
+
+/// Oscillator shape for pitched voices. `Custom` holds the sine amplitudes
+/// of the first `HARMONICS` harmonics and plays through a `PeriodicWave`.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Wave {
+    Sine,
+    Triangle,
+    Square,
+    Sawtooth,
+    Custom(Wavetable),
+}
+
+/// Harmonic amplitudes, fundamental first, along with the peak of the wave
+/// they add up to, which is found once when the table is built. Serialized
+/// as the bare amplitudes.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(from = "[f32; HARMONICS]", into = "[f32; HARMONICS]")]
+pub struct Wavetable {
+    harmonics: [f32; HARMONICS],
+    peak: f32,
+}
+
+impl From<[f32; HARMONICS]> for Wavetable {
+    fn from(harmonics: [f32; HARMONICS]) -> Self {
+        let peak = (0..PEAK_STEPS)
+            .map(|step| additive(&harmonics, step as f32 / PEAK_STEPS as f32).abs())
+            .fold(0.0, f32::max);
+        Wavetable { harmonics, peak }
+    }
+}
+
+impl From<Wavetable> for [f32; HARMONICS] {
+    fn from(table: Wavetable) -> Self {
+        table.harmonics
+    }
+}
+
+impl Wavetable {
+    pub fn harmonics(&self) -> &[f32; HARMONICS] {
+        &self.harmonics
+    }
+}
+
+impl FromStr for Wave {
+    type Err = Error;
+
+    fn from_str(wave: &str) -> Result<Self, Self::Err> {
+        match wave {
+            "sine" => Ok(Wave::Sine),
+            "triangle" => Ok(Wave::Triangle),
+            "square" => Ok(Wave::Square),
+            "sawtooth" => Ok(Wave::Sawtooth),
+            _ => Err(Error::UnknownWave(wave.to_string())),
+        }
+    }
+}
+
+impl Wave {
+    /// A custom wave from up to `HARMONICS` amplitudes; missing ones are 0.
+    pub fn custom(harmonics: &[f32]) -> Result<Wave, Error> {
+        check_range(
+            "harmonic count",
+            harmonics.len() as f64,
+            0.0,
+            HARMONICS as f64,
+        )?;
+        let mut table = [0.0; HARMONICS];
+        table[..harmonics.len()].copy_from_slice(harmonics);
+        let wave = Wave::Custom(table.into());
+        wave.validate()?;
+        Ok(wave)
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        if let Wave::Custom(table) = self {
+            for harmonic in table.harmonics().iter() {
+                HARMONIC.check(*harmonic)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the oscillator to this shape.
+    pub fn apply(&self, ctx: &AudioContext, osc: &OscillatorNode) -> Result<(), JsValue> {
+        match self {
+            Wave::Sine => osc.set_type(OscillatorType::Sine),
+            Wave::Triangle => osc.set_type(OscillatorType::Triangle),
+            Wave::Square => osc.set_type(OscillatorType::Square),
+            Wave::Sawtooth => osc.set_type(OscillatorType::Sawtooth),
+            Wave::Custom(table) => {
+                // Index 0 is the DC offset, which a drum never wants.
+                let mut real = [0.0; HARMONICS + 1];
+                let mut imag = [0.0; HARMONICS + 1];
+                imag[1..].copy_from_slice(table.harmonics());
+                let wave = ctx.create_periodic_wave(&mut real, &mut imag)?;
+                osc.set_periodic_wave(&wave);
+            }
+        }
+        Ok(())
+    }
+
+    /// The value of one cycle at `phase` in 0..1, matching what the browser
+    /// oscillator plays, for rendering without Web Audio.
+    pub fn sample(&self, phase: f32) -> f32 {
+        let phase = phase.rem_euclid(1.0);
+        match self {
+            Wave::Sine => (2.0 * PI * phase).sin(),
+            Wave::Triangle => 1.0 - 4.0 * ((phase + 0.25).fract() - 0.5).abs(),
+            Wave::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Wave::Sawtooth => 2.0 * (phase + 0.5).fract() - 1.0,
+            Wave::Custom(table) => {
+                // Web Audio scales a periodic wave so its time-domain peak is 1.
+                if table.peak == 0.0 {
+                    return 0.0;
+                }
+                additive(&table.harmonics, phase) / table.peak
+            }
+        }
+    }
+}
+
+fn additive(harmonics: &[f32; HARMONICS], phase: f32) -> f32 {
+    harmonics
+        .iter()
+        .enumerate()
+        .map(|(i, amplitude)| amplitude * (2.0 * PI * (i + 1) as f32 * phase).sin())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn shapes_start_at_zero_and_peak_a_quarter_in() {
+        for wave in [Wave::Sine, Wave::Triangle, Wave::custom(&[1.0]).unwrap()].iter() {
+            assert!(close(wave.sample(0.0), 0.0), "{:?}", wave);
+            assert!(close(wave.sample(0.25), 1.0), "{:?}", wave);
+            assert!(close(wave.sample(0.75), -1.0), "{:?}", wave);
+        }
+        assert_eq!(Wave::Square.sample(0.25), 1.0);
+        assert_eq!(Wave::Square.sample(0.75), -1.0);
+        assert!(close(Wave::Sawtooth.sample(0.0), 0.0));
+        assert!(close(Wave::Sawtooth.sample(0.25), 0.5));
+        assert!(close(Wave::Sawtooth.sample(0.75), -0.5));
+    }
+
+    #[test]
+    fn custom_shapes_peak_at_one() {
+        // sin(x) + sin(3x) peaks at about 1.5396 near a tenth of the cycle.
+        let wave = Wave::custom(&[1.0, 0.0, 1.0]).unwrap();
+        assert!((wave.sample(0.098) - 1.0).abs() < 1e-3);
+        assert!(close(wave.sample(0.25), 0.0));
+        for step in 0..64 {
+            assert!(wave.sample(step as f32 / 64.0).abs() <= 1.0);
+        }
+        assert_eq!(Wave::custom(&[]).unwrap().sample(0.25), 0.0);
+    }
+
+    #[test]
+    fn parses_and_serializes_shapes() {
+        assert_eq!("square".parse(), Ok(Wave::Square));
+        assert_eq!(
+            "noise".parse::<Wave>(),
+            Err(Error::UnknownWave("noise".to_string()))
+        );
+        let custom = Wave::custom(&[1.0, 0.5]).unwrap();
+        let json = serde_json::to_string(&custom).unwrap();
+        assert_eq!(json, r#"{"custom":[1.0,0.5,0.0,0.0,0.0,0.0,0.0,0.0]}"#);
+        assert_eq!(serde_json::from_str::<Wave>(&json).unwrap(), custom);
+        assert_eq!(
+            serde_json::to_string(&Wave::Sawtooth).unwrap(),
+            r#""sawtooth""#
+        );
+        assert!(Wave::custom(&[2.0]).is_err());
+        assert!(Wave::custom(&[0.0; HARMONICS + 1]).is_err());
+    }
+}
@@ -0,0 +1,233 @@
+use crate::error::Error;
+use crate::sounds::SoundParams;
+use serde::{Deserialize, Serialize};
+
+pub const PATTERN_VERSION: u32 = 1;
+pub const PATTERN_STEPS: usize = 16;
+pub const PATTERN_SCHEMA: &str = include_str!("../schema/pattern.schema.json");
+
+pub type Sequence = [Step; PATTERN_STEPS];
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Default)]
+#[serde(tag = "step_type", rename_all = "snake_case")]
+pub enum Step {
+    #[default]
+    Off,
+    Trigger,
+    Lock {
+        params: SoundParams,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Pattern {
+    pub version: u32,
+    pub steps: Vec<Step>,
+}
+
+impl Pattern {
+    pub fn from_sequence(sequence: &Sequence) -> Pattern {
+        Pattern {
+            version: PATTERN_VERSION,
+            steps: sequence.to_vec(),
+        }
+    }
+
+    pub fn from_json(json: &str) -> Result<Pattern, Error> {
+        serde_json::from_str(json).map_err(|err| Error::MalformedSteps(err.to_string()))
+    }
+
+    pub fn to_sequence(&self, track: &str) -> Result<Sequence, Error> {
+        if self.version != PATTERN_VERSION {
+            return Err(Error::UnsupportedVersion(self.version));
+        }
+        if self.steps.len() != PATTERN_STEPS {
+            return Err(Error::StepCount {
+                expected: PATTERN_STEPS,
+                found: self.steps.len(),
+            });
+        }
+        let mut sequence: Sequence = Default::default();
+        for (slot, step) in sequence.iter_mut().zip(&self.steps) {
+            if let Step::Lock { params } = step {
+                if params.name() != track {
+                    return Err(Error::MismatchedSound {
+                        track: track.to_string(),
+                        sound: params.name(),
+                    });
+                }
+                params.validate()?;
+            }
+            *slot = *step;
+        }
+        Ok(sequence)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sounds::clap::ClapParams;
+    use crate::sounds::cowbell::CowbellParams;
+    use crate::sounds::cymbal::CymbalParams;
+    use crate::sounds::fm::FmParams;
+    use crate::sounds::hat::HatParams;
+    use crate::sounds::kick::KickParams;
+    use crate::sounds::rimshot::RimshotParams;
+    use crate::sounds::sampler::SamplerParams;
+    use crate::sounds::snare::SnareParams;
+    use crate::sounds::tom::TomParams;
+    use crate::sounds::wave::Wave;
+
+    fn pattern_with(lock: SoundParams) -> Pattern {
+        let mut sequence: Sequence = Default::default();
+        sequence[0] = Step::Trigger;
+        sequence[4] = Step::Lock { params: lock };
+        Pattern::from_sequence(&sequence)
+    }
+
+    #[test]
+    fn steps_round_trip() {
+        let locks = [
+            SoundParams::Kick(KickParams {
+                wave: Wave::custom(&[1.0, 0.0, 0.3]).unwrap(),
+                ..KickParams::default()
+            }),
+            SoundParams::Snare(SnareParams::default()),
+            SoundParams::Hat(HatParams::default()),
+            SoundParams::Clap(ClapParams::default()),
+            SoundParams::Tom(TomParams {
+                wave: Wave::Sawtooth,
+                ..TomParams::default()
+            }),
+            SoundParams::Cymbal(CymbalParams {
+                open: true,
+                ..CymbalParams::default()
+            }),
+            SoundParams::Cowbell(CowbellParams::default()),
+            SoundParams::Rimshot(RimshotParams::default()),
+            SoundParams::Sampler(SamplerParams {
+                reverse: true,
+                ..SamplerParams::default()
+            }),
+            SoundParams::Fm(FmParams::default()),
+        ];
+        for lock in locks.iter() {
+            let pattern = pattern_with(*lock);
+            let json = serde_json::to_string(&pattern).unwrap();
+            assert_eq!(Pattern::from_json(&json).unwrap(), pattern);
+        }
+    }
+
+    #[test]
+    fn steps_use_tagged_format() {
+        let json =
+            serde_json::to_value(pattern_with(SoundParams::Hat(HatParams::default()))).unwrap();
+        assert_eq!(json["version"], 1);
+        assert_eq!(json["steps"][0]["step_type"], "trigger");
+        assert_eq!(json["steps"][1]["step_type"], "off");
+        assert_eq!(json["steps"][4]["step_type"], "lock");
+        assert_eq!(json["steps"][4]["params"]["sound"], "hat");
+    }
+
+    #[test]
+    fn rejects_unknown_versions_and_step_types() {
+        let mut pattern = pattern_with(SoundParams::Kick(KickParams::default()));
+        pattern.version = 2;
+        assert_eq!(
+            pattern.to_sequence("kick"),
+            Err(Error::UnsupportedVersion(2))
+        );
+        let json = r#"{"version":1,"steps":[{"step_type":"lock_trigger"}]}"#;
+        assert!(Pattern::from_json(json).is_err());
+    }
+
+    #[test]
+    fn maps_legacy_snare_blend() {
+        let json = r#"{"version":1,"steps":[{"step_type":"lock","params":{"sound":"snare","blend":0.8}}]}"#;
+        let pattern = Pattern::from_json(json).unwrap();
+        assert_eq!(
+            pattern.steps[0],
+            Step::Lock {
+                params: SoundParams::Snare(SnareParams {
+                    tone: 1.0 - 0.8,
+                    snappy: 0.8,
+                    ..SnareParams::default()
+                })
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_locks_for_other_sounds() {
+        let pattern = pattern_with(SoundParams::Snare(SnareParams::default()));
+        assert!(pattern.to_sequence("snare").is_ok());
+        assert_eq!(
+            pattern.to_sequence("kick"),
+            Err(Error::MismatchedSound {
+                track: "kick".to_string(),
+                sound: "snare",
+            })
+        );
+    }
+
+    #[test]
+    fn schema_lists_every_step_type() {
+        let schema: serde_json::Value = serde_json::from_str(PATTERN_SCHEMA).unwrap();
+        let step_types: Vec<&str> = schema["definitions"]["step"]["oneOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|step| step["properties"]["step_type"]["const"].as_str().unwrap())
+            .collect();
+        assert_eq!(step_types, ["off", "trigger", "lock"]);
+    }
+
+    #[test]
+    fn schema_requires_what_the_parser_requires() {
+        let schema: serde_json::Value = serde_json::from_str(PATTERN_SCHEMA).unwrap();
+        let sounds = [
+            SoundParams::Kick(KickParams::default()),
+            SoundParams::Snare(SnareParams::default()),
+            SoundParams::Hat(HatParams::default()),
+            SoundParams::Clap(ClapParams::default()),
+            SoundParams::Tom(TomParams::default()),
+            SoundParams::Cymbal(CymbalParams::default()),
+            SoundParams::Cowbell(CowbellParams::default()),
+            SoundParams::Rimshot(RimshotParams::default()),
+            SoundParams::Sampler(SamplerParams::default()),
+            SoundParams::Fm(FmParams::default()),
+        ];
+        for entry in schema["definitions"]["params"]["oneOf"].as_array().unwrap() {
+            let name = entry["properties"]["sound"]["const"].as_str().unwrap();
+            let params = sounds.iter().find(|params| params.name() == name).unwrap();
+            let full = serde_json::to_value(params).unwrap();
+            let required: Vec<&str> = entry["required"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|field| field.as_str().unwrap())
+                .collect();
+            let mut minimal = full.clone();
+            minimal
+                .as_object_mut()
+                .unwrap()
+                .retain(|field, _| required.contains(&field.as_str()));
+            assert!(
+                serde_json::from_value::<SoundParams>(minimal).is_ok(),
+                "{}",
+                name
+            );
+            for field in required.iter().filter(|field| **field != "sound") {
+                let mut missing = full.clone();
+                missing.as_object_mut().unwrap().remove(*field);
+                assert!(
+                    serde_json::from_value::<SoundParams>(missing).is_err(),
+                    "{}.{}",
+                    name,
+                    field
+                );
+            }
+        }
+    }
+}
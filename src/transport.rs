@@ -0,0 +1,273 @@
+use crate::error::{check_range, Error};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+pub const STEPS_PER_BEAT: u32 = 4;
+
+/// Musical clock shared by every track. Beats are counted from `start` and
+/// carried across tempo changes so positions never jump.
+pub struct Transport {
+    pub tempo: f32,
+    pub beats_per_bar: u8,
+    origin: Option<f64>,
+    beats_at_origin: f64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Position {
+    pub time: f64,
+    pub bar: u32,
+    pub beat: u32,
+    pub step: u32,
+    pub loops: BTreeMap<&'static str, u32>,
+}
+
+impl Transport {
+    pub fn new(tempo: f32) -> Self {
+        Transport {
+            tempo,
+            beats_per_bar: 4,
+            origin: None,
+            beats_at_origin: 0.0,
+        }
+    }
+
+    /// Starts counting at `time` from `beats` into the song.
+    pub fn start(&mut self, time: f64, beats: f64) {
+        self.origin = Some(time);
+        self.beats_at_origin = beats;
+    }
+
+    pub fn stop(&mut self) {
+        self.origin = None;
+        self.beats_at_origin = 0.0;
+    }
+
+    /// Freezes the position reached at `time` until `resume`.
+    pub fn pause(&mut self, time: f64) {
+        self.beats_at_origin = self.beats_at(time);
+        self.origin = None;
+    }
+
+    pub fn resume(&mut self, time: f64) {
+        if self.origin.is_none() {
+            self.origin = Some(time);
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.origin.is_some()
+    }
+
+    pub fn set_tempo(&mut self, tempo: f32, time: f64) {
+        if let Some(origin) = self.origin {
+            self.beats_at_origin = self.beats_at(time);
+            self.origin = Some(time.max(origin));
+        }
+        self.tempo = tempo;
+    }
+
+    pub fn set_beats_per_bar(&mut self, beats_per_bar: u8) -> Result<(), Error> {
+        check_range("beats per bar", beats_per_bar.into(), 1.0, 16.0)?;
+        self.beats_per_bar = beats_per_bar;
+        Ok(())
+    }
+
+    pub fn bar_duration(&self) -> f64 {
+        self.beat_duration() * self.beats_per_bar as f64
+    }
+
+    pub fn beat_duration(&self) -> f64 {
+        60.0 / self.tempo as f64
+    }
+
+    pub fn beats_at(&self, time: f64) -> f64 {
+        match self.origin {
+            Some(origin) if time > origin => {
+                self.beats_at_origin + (time - origin) / self.beat_duration()
+            }
+            _ => self.beats_at_origin,
+        }
+    }
+
+    /// Inverse of `beats_at`, or `None` while stopped or paused.
+    pub fn time_at(&self, beats: f64) -> Option<f64> {
+        self.origin
+            .map(|origin| origin + (beats - self.beats_at_origin) * self.beat_duration())
+    }
+
+    pub fn bar_at(&self, time: f64) -> u32 {
+        (self.beats_at(time) / self.beats_per_bar as f64) as u32
+    }
+
+    pub fn position(&self, time: f64, loops: BTreeMap<&'static str, u32>) -> Position {
+        let steps = (self.beats_at(time) * STEPS_PER_BEAT as f64) as u32;
+        let steps_per_bar = STEPS_PER_BEAT * self.beats_per_bar as u32;
+        Position {
+            time,
+            bar: steps / steps_per_bar,
+            beat: steps % steps_per_bar / STEPS_PER_BEAT,
+            step: steps % STEPS_PER_BEAT,
+            loops,
+        }
+    }
+}
+
+/// Places a track's steps on the transport's beat grid. Every step's beat is
+/// worked out from an integer count rather than by adding up step lengths,
+/// so tracks of any length land on the same bar lines however long they run.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StepClock {
+    anchor: f64,
+    count: i64,
+    steps: i64,
+    beats_per_bar: i64,
+}
+
+impl StepClock {
+    pub fn new(steps: i8, beats_per_bar: u8) -> Self {
+        StepClock {
+            anchor: 0.0,
+            count: 0,
+            steps: steps.into(),
+            beats_per_bar: beats_per_bar.into(),
+        }
+    }
+
+    /// Anchors the grid at the bar starting on `bar_start` and moves to the
+    /// first step at or after `beats`. Returns that step's index in the bar.
+    pub fn start(&mut self, bar_start: f64, beats: f64) -> i64 {
+        self.anchor = bar_start;
+        self.count =
+            ((beats - bar_start) * self.steps as f64 / self.beats_per_bar as f64).ceil() as i64;
+        self.count
+    }
+
+    /// Beat of the next step.
+    pub fn beats(&self) -> f64 {
+        self.beats_of(self.count)
+    }
+
+    pub fn previous_beats(&self) -> f64 {
+        self.beats_of(self.count - 1)
+    }
+
+    fn beats_of(&self, count: i64) -> f64 {
+        self.anchor + (count * self.beats_per_bar) as f64 / self.steps as f64
+    }
+
+    pub fn advance(&mut self) {
+        self.count += 1;
+    }
+
+    pub fn rewind(&mut self) {
+        self.count -= 1;
+    }
+
+    /// Changes the step length from the next step on, keeping the bar lines
+    /// where they were. The next step becomes the first one of the new grid
+    /// at or after the old next step. Returns its index in the bar, which is
+    /// `steps` when it falls on the next bar line, or `None` if nothing
+    /// changed.
+    pub fn retune(&mut self, steps: i8, beats_per_bar: u8) -> Option<i64> {
+        let (steps, beats_per_bar) = (steps.into(), beats_per_bar.into());
+        if (steps, beats_per_bar) == (self.steps, self.beats_per_bar) {
+            return None;
+        }
+        let next = self.beats();
+        self.anchor += (self.count.div_euclid(self.steps) * self.beats_per_bar) as f64;
+        self.steps = steps;
+        self.beats_per_bar = beats_per_bar;
+        self.count = ((next - self.anchor) * steps as f64 / beats_per_bar as f64).ceil() as i64;
+        Some(self.count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_bars_beats_and_steps() {
+        let mut transport = Transport::new(120.0);
+        transport.start(1.0, 0.0);
+        let position = transport.position(1.0 + 2.0 + 0.5 + 0.125, BTreeMap::new());
+        assert_eq!((position.bar, position.beat, position.step), (1, 1, 1));
+    }
+
+    #[test]
+    fn tempo_changes_keep_the_position() {
+        let mut transport = Transport::new(120.0);
+        transport.start(0.0, 0.0);
+        transport.set_tempo(60.0, 1.0);
+        assert_eq!(transport.beats_at(1.0), 2.0);
+        assert_eq!(transport.beats_at(2.0), 3.0);
+        assert_eq!(transport.time_at(3.0), Some(2.0));
+    }
+
+    #[test]
+    fn pause_holds_the_position_until_resumed() {
+        let mut transport = Transport::new(60.0);
+        transport.start(0.0, 4.0);
+        transport.pause(1.5);
+        assert_eq!(transport.beats_at(10.0), 5.5);
+        transport.resume(10.0);
+        assert_eq!(transport.beats_at(11.0), 6.5);
+    }
+
+    #[test]
+    fn polyrhythms_stay_aligned_for_hours() {
+        let mut transport = Transport::new(120.0);
+        transport.start(0.0, 0.0);
+        let mut clocks = [
+            StepClock::new(16, 4),
+            StepClock::new(12, 4),
+            StepClock::new(10, 4),
+        ];
+        let mut time = 0.0;
+        for bar in 1..=6000 {
+            if bar % 500 == 0 {
+                transport.set_tempo(90.0 + (bar % 7) as f32 * 10.0, time);
+            }
+            let starts: Vec<f64> = clocks
+                .iter_mut()
+                .map(|clock| {
+                    for _ in 0..clock.steps {
+                        clock.advance();
+                    }
+                    transport.time_at(clock.beats()).unwrap()
+                })
+                .collect();
+            assert_eq!(starts[0], starts[1]);
+            assert_eq!(starts[0], starts[2]);
+            assert!(starts[0] > time);
+            time = starts[0];
+        }
+        assert!(time > 3.0 * 60.0 * 60.0);
+        assert_eq!(transport.bar_at(time), 6000);
+    }
+
+    #[test]
+    fn shortening_a_track_mid_bar_keeps_its_bar_lines() {
+        let mut clock = StepClock::new(16, 4);
+        clock.start(0.0, 0.0);
+        for _ in 0..16 + 5 {
+            clock.advance();
+        }
+        assert_eq!(clock.retune(12, 4), Some(4));
+        assert_eq!(clock.beats(), 4.0 + 4.0 / 3.0);
+        for _ in 4..12 {
+            clock.advance();
+        }
+        assert_eq!(clock.beats(), 8.0);
+        assert_eq!(clock.retune(12, 4), None);
+    }
+
+    #[test]
+    fn time_signature_changes_the_bar_length() {
+        let mut transport = Transport::new(60.0);
+        transport.set_beats_per_bar(3).unwrap();
+        assert_eq!(transport.bar_duration(), 3.0);
+        assert!(transport.set_beats_per_bar(0).is_err());
+    }
+}
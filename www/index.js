@@ -19,6 +19,7 @@ app.ports.stopSequence.subscribe(function () {
     clearInterval(interval);
     sound.stop();
     interval = null;
+    pendingEvents = [];
   }
 });
 app.ports.updateKickVolume.subscribe(function (volume) {
@@ -36,20 +37,20 @@ app.ports.updateKick.subscribe(function (params) {
 });
 app.ports.updateSnare.subscribe(function (params) {
   let { freq, blend, decay, punch, volume } = params;
-  sound.update_snare(freq, blend, decay, punch, volume);
+  sound.update_snare(freq, 1 - blend, blend, decay, punch, volume);
 });
 app.ports.updateHat.subscribe(function (params) {
   let { freq, decay, punch, volume } = params;
   sound.update_hat(freq, decay, punch, volume);
 });
-app.ports.updateKickSequence.subscribe(function (sequence) {
-  sound.update_kick_steps(sequence)
+app.ports.updateKickSequence.subscribe(function (pattern) {
+  sound.update_steps("kick", pattern)
 });
-app.ports.updateSnareSequence.subscribe(function (sequence) {
-  sound.update_snare_steps(sequence)
+app.ports.updateSnareSequence.subscribe(function (pattern) {
+  sound.update_steps("snare", pattern)
 });
-app.ports.updateHatSequence.subscribe(function (sequence) {
-  sound.update_hat_steps(sequence)
+app.ports.updateHatSequence.subscribe(function (pattern) {
+  sound.update_steps("hat", pattern)
 });
 app.ports.updateKickSequencerLength.subscribe(function (sequencerLength) {
   sound.update_sequencer_length("kick", sequencerLength)
@@ -61,28 +62,39 @@ app.ports.updateHatSequencerLength.subscribe(function (sequencerLength) {
   sound.update_sequencer_length("hat", sequencerLength)
 });
 app.ports.updateKickOffset.subscribe(function (offset) {
-  sound.update_offset("kick", offset)
+  sound.update_offset("kick", offset * 1000, "ms")
 });
 app.ports.updateSnareOffset.subscribe(function (offset) {
-  sound.update_offset("snare", offset)
+  sound.update_offset("snare", offset * 1000, "ms")
 });
 app.ports.updateHatOffset.subscribe(function (offset) {
-  sound.update_offset("hat", offset)
+  sound.update_offset("hat", offset * 1000, "ms")
 });
 app.ports.updateTempo.subscribe(function (tempo) {
   sound.update_tempo(tempo)
 });
 
-function getSteps() {
-  const kickStep = sound.get_steps("kick")
-  app.ports.receiveKickStepNumber.send(kickStep);
-  const snareStep = sound.get_steps("snare")
-  app.ports.receiveSnareStepNumber.send(snareStep);
-  const hatStep = sound.get_steps("hat")
-  app.ports.receiveHatStepNumber.send(hatStep);
-  window.requestAnimationFrame(getSteps)
+const stepPorts = {
+  kick: app.ports.receiveKickStepNumber,
+  snare: app.ports.receiveSnareStepNumber,
+  hat: app.ports.receiveHatStepNumber,
+};
+let pendingEvents = [];
+
+function showSteps() {
+  pendingEvents = pendingEvents.concat(sound.drain_events());
+  const now = sound.current_time();
+  while (pendingEvents.length > 0 && pendingEvents[0].time <= now) {
+    const event = pendingEvents.shift();
+    // Only some tracks have a step display.
+    const port = stepPorts[event.track];
+    if (port) {
+      port.send(event.step);
+    }
+  }
+  window.requestAnimationFrame(showSteps)
 }
-window.requestAnimationFrame(getSteps)
+window.requestAnimationFrame(showSteps)
 
 // if ("serviceWorker" in navigator) {
 //   window.addEventListener("load", () => {
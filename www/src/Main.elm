@@ -6,6 +6,7 @@ import Html exposing (Html, button, div, input, span, text)
 import Html.Attributes as A exposing (disabled)
 import Html.Events as Events exposing (onBlur, onClick, onInput, onMouseDown, onMouseEnter, onMouseLeave, onMouseUp)
 import Json.Decode as Decode
+import Json.Encode as Encode
 import Parser exposing (..)
 
 
@@ -28,13 +29,13 @@ port updateSnare : SnareParams -> Cmd msg
 port updateHat : HatParams -> Cmd msg
 
 
-port updateKickSequence : List KickParamsOut -> Cmd msg
+port updateKickSequence : Encode.Value -> Cmd msg
 
 
-port updateSnareSequence : List SnareParamsOut -> Cmd msg
+port updateSnareSequence : Encode.Value -> Cmd msg
 
 
-port updateHatSequence : List HatParamsOut -> Cmd msg
+port updateHatSequence : Encode.Value -> Cmd msg
 
 
 port updateKickSequencerLength : Int -> Cmd msg
@@ -107,17 +108,6 @@ type alias KickParams =
     }
 
 
-type alias KickParamsOut =
-    { freq : Float
-    , pitch : Float
-    , wave : String
-    , decay : Float
-    , punch : Float
-    , volume : Float
-    , step_type : String
-    }
-
-
 type alias KickParamsStrings =
     { freq : String
     , pitch : String
@@ -137,16 +127,6 @@ type alias SnareParams =
     }
 
 
-type alias SnareParamsOut =
-    { freq : Float
-    , blend : Float
-    , decay : Float
-    , punch : Float
-    , volume : Float
-    , step_type : String
-    }
-
-
 type alias SnareParamsStrings =
     { freq : String
     , blend : String
@@ -164,15 +144,6 @@ type alias HatParams =
     }
 
 
-type alias HatParamsOut =
-    { freq : Float
-    , decay : Float
-    , punch : Float
-    , volume : Float
-    , step_type : String
-    }
-
-
 type alias HatParamsStrings =
     { freq : String
     , decay : String
@@ -284,130 +255,63 @@ emptySequencer =
     List.map (\_ -> EmptyStep) (List.range 0 15)
 
 
-transformKickStep : KickParams -> Step -> KickParamsOut
-transformKickStep kickParams step =
-    case step of
-        Trigger ->
-            { freq = kickParams.freq
-            , pitch = kickParams.pitch
-            , wave = kickParams.wave
-            , decay = kickParams.decay
-            , punch = kickParams.punch
-            , volume = kickParams.volume
-            , step_type = "trigger"
-            }
-
-        LockTrigger sound ->
-            case sound of
-                KickSound kick ->
-                    { freq = kick.freq
-                    , pitch = kick.pitch
-                    , wave = kick.wave
-                    , decay = kick.decay
-                    , punch = kick.punch
-                    , volume = kick.volume
-                    , step_type = "lock_trigger"
-                    }
-
-                _ ->
-                    { freq = 0
-                    , pitch = 0
-                    , wave = ""
-                    , decay = 0
-                    , punch = 0
-                    , volume = 0
-                    , step_type = "empty"
-                    }
-
-        EmptyStep ->
-            { freq = 0
-            , pitch = 0
-            , wave = ""
-            , decay = 0
-            , punch = 0
-            , volume = 0
-            , step_type = "empty"
-            }
+encodePattern : List Step -> Encode.Value
+encodePattern steps =
+    Encode.object
+        [ ( "version", Encode.int 1 )
+        , ( "steps", Encode.list encodeStep steps )
+        ]
 
 
-transformSnareStep : SnareParams -> Step -> SnareParamsOut
-transformSnareStep snareParams step =
+encodeStep : Step -> Encode.Value
+encodeStep step =
     case step of
         Trigger ->
-            { freq = snareParams.freq
-            , blend = snareParams.blend
-            , decay = snareParams.decay
-            , punch = snareParams.punch
-            , volume = snareParams.volume
-            , step_type = "trigger"
-            }
+            Encode.object [ ( "step_type", Encode.string "trigger" ) ]
 
         LockTrigger sound ->
-            case sound of
-                SnareSound snare ->
-                    { freq = snare.freq
-                    , blend = snare.blend
-                    , decay = snare.decay
-                    , punch = snare.punch
-                    , volume = snare.volume
-                    , step_type = "lock_trigger"
-                    }
-
-                _ ->
-                    { freq = 0
-                    , blend = 0
-                    , decay = 0
-                    , punch = 0
-                    , volume = 0
-                    , step_type = "empty"
-                    }
+            Encode.object
+                [ ( "step_type", Encode.string "lock" )
+                , ( "params", encodeSound sound )
+                ]
 
         EmptyStep ->
-            { freq = 0
-            , blend = 0
-            , decay = 0
-            , punch = 0
-            , volume = 0
-            , step_type = "empty"
-            }
-
-
-transformHatStep : HatParams -> Step -> HatParamsOut
-transformHatStep hatParams step =
-    case step of
-        Trigger ->
-            { freq = hatParams.freq
-            , decay = hatParams.decay
-            , punch = hatParams.punch
-            , volume = hatParams.volume
-            , step_type = "trigger"
-            }
-
-        LockTrigger sound ->
-            case sound of
-                HatSound hat ->
-                    { freq = hat.freq
-                    , decay = hat.decay
-                    , punch = hat.punch
-                    , volume = hat.volume
-                    , step_type = "lock_trigger"
-                    }
+            Encode.object [ ( "step_type", Encode.string "off" ) ]
+
+
+encodeSound : Sound -> Encode.Value
+encodeSound sound =
+    case sound of
+        KickSound kick ->
+            Encode.object
+                [ ( "sound", Encode.string "kick" )
+                , ( "freq", Encode.float kick.freq )
+                , ( "pitch", Encode.float kick.pitch )
+                , ( "wave", Encode.string kick.wave )
+                , ( "decay", Encode.float kick.decay )
+                , ( "punch", Encode.float kick.punch )
+                , ( "volume", Encode.float kick.volume )
+                ]
 
-                _ ->
-                    { freq = 0
-                    , decay = 0
-                    , punch = 0
-                    , volume = 0
-                    , step_type = "empty"
-                    }
+        SnareSound snare ->
+            Encode.object
+                [ ( "sound", Encode.string "snare" )
+                , ( "freq", Encode.float snare.freq )
+                , ( "tone", Encode.float (1 - snare.blend) )
+                , ( "snappy", Encode.float snare.blend )
+                , ( "decay", Encode.float snare.decay )
+                , ( "punch", Encode.float snare.punch )
+                , ( "volume", Encode.float snare.volume )
+                ]
 
-        EmptyStep ->
-            { freq = 0
-            , decay = 0
-            , punch = 0
-            , volume = 0
-            , step_type = "empty"
-            }
+        HatSound hat ->
+            Encode.object
+                [ ( "sound", Encode.string "hat" )
+                , ( "freq", Encode.float hat.freq )
+                , ( "decay", Encode.float hat.decay )
+                , ( "punch", Encode.float hat.punch )
+                , ( "volume", Encode.float hat.volume )
+                ]
 
 
 rotateSteps : List Step -> List Step
@@ -435,8 +339,8 @@ rotateSteps steps =
     newSteps
 
 
-compileKickSteps : List Step -> KickParams -> KickParams -> Int -> ( List Step, List KickParamsOut )
-compileKickSteps steps kick kickEdit stepNumber =
+compileKickSteps : List Step -> KickParams -> Int -> ( List Step, Encode.Value )
+compileKickSteps steps kickEdit stepNumber =
     let
         stepArray =
             Array.fromList steps
@@ -447,11 +351,11 @@ compileKickSteps steps kick kickEdit stepNumber =
         newSteps =
             Array.toList <| Array.set stepNumber newStep stepArray
     in
-    ( newSteps, List.map (\a -> transformKickStep kick a) newSteps )
+    ( newSteps, encodePattern newSteps )
 
 
-compileSnareSteps : List Step -> SnareParams -> SnareParams -> Int -> ( List Step, List SnareParamsOut )
-compileSnareSteps steps snare snareEdit stepNumber =
+compileSnareSteps : List Step -> SnareParams -> Int -> ( List Step, Encode.Value )
+compileSnareSteps steps snareEdit stepNumber =
     let
         stepArray =
             Array.fromList steps
@@ -462,11 +366,11 @@ compileSnareSteps steps snare snareEdit stepNumber =
         newSteps =
             Array.toList <| Array.set stepNumber newStep stepArray
     in
-    ( newSteps, List.map (\a -> transformSnareStep snare a) newSteps )
+    ( newSteps, encodePattern newSteps )
 
 
-compileHatSteps : List Step -> HatParams -> HatParams -> Int -> ( List Step, List HatParamsOut )
-compileHatSteps steps hat hatEdit stepNumber =
+compileHatSteps : List Step -> HatParams -> Int -> ( List Step, Encode.Value )
+compileHatSteps steps hatEdit stepNumber =
     let
         stepArray =
             Array.fromList steps
@@ -477,7 +381,7 @@ compileHatSteps steps hat hatEdit stepNumber =
         newSteps =
             Array.toList <| Array.set stepNumber newStep stepArray
     in
-    ( newSteps, List.map (\a -> transformHatStep hat a) newSteps )
+    ( newSteps, encodePattern newSteps )
 
 
 clipValues : comparable -> comparable -> comparable -> comparable
@@ -657,7 +561,7 @@ update msg model =
                                     model.kickSequencer
 
                                 ( steps, compiledSteps ) =
-                                    compileKickSteps kickSequencer.steps model.kick newKick stepNumber
+                                    compileKickSteps kickSequencer.steps newKick stepNumber
                             in
                             ( { model | kickEdit = Just newKick, kickSequencer = { kickSequencer | steps = steps } }, updateKickSequence compiledSteps )
 
@@ -721,7 +625,7 @@ update msg model =
                                     model.snareSequencer
 
                                 ( steps, compiledSteps ) =
-                                    compileSnareSteps snareSequencer.steps model.snare newSnare stepNumber
+                                    compileSnareSteps snareSequencer.steps newSnare stepNumber
                             in
                             ( { model | snareEdit = Just newSnare, snareSequencer = { snareSequencer | steps = steps } }, updateSnareSequence compiledSteps )
 
@@ -777,7 +681,7 @@ update msg model =
                                     model.hatSequencer
 
                                 ( steps, compiledSteps ) =
-                                    compileHatSteps hatSequencer.steps model.hat newHat stepNumber
+                                    compileHatSteps hatSequencer.steps newHat stepNumber
                             in
                             ( { model | hatEdit = Just newHat, hatSequencer = { hatSequencer | steps = steps } }, updateHatSequence compiledSteps )
 
@@ -869,7 +773,7 @@ update msg model =
                 kickSequencer =
                     model.kickSequencer
             in
-            ( { model | kickSequencer = { kickSequencer | steps = newSteps, editingStep = editingStep }, kickEdit = kickEdit, clicking = True }, updateKickSequence (List.map (\a -> transformKickStep model.kick a) newSteps) )
+            ( { model | kickSequencer = { kickSequencer | steps = newSteps, editingStep = editingStep }, kickEdit = kickEdit, clicking = True }, updateKickSequence (encodePattern newSteps) )
 
         SnareSteps value ->
             let
@@ -932,7 +836,7 @@ update msg model =
                 snareSequencer =
                     model.snareSequencer
             in
-            ( { model | snareSequencer = { snareSequencer | steps = newSteps, editingStep = editingStep }, snareEdit = snareEdit, clicking = True }, updateSnareSequence (List.map (\a -> transformSnareStep model.snare a) newSteps) )
+            ( { model | snareSequencer = { snareSequencer | steps = newSteps, editingStep = editingStep }, snareEdit = snareEdit, clicking = True }, updateSnareSequence (encodePattern newSteps) )
 
         HatSteps value ->
             let
@@ -995,7 +899,7 @@ update msg model =
                 hatSequencer =
                     model.hatSequencer
             in
-            ( { model | hatSequencer = { hatSequencer | steps = newSteps, editingStep = editingStep }, hatEdit = hatEdit, clicking = True }, updateHatSequence (List.map (\a -> transformHatStep model.hat a) newSteps) )
+            ( { model | hatSequencer = { hatSequencer | steps = newSteps, editingStep = editingStep }, hatEdit = hatEdit, clicking = True }, updateHatSequence (encodePattern newSteps) )
 
         MoveKick value ->
             let
@@ -1021,7 +925,7 @@ update msg model =
                 kickSequencer =
                     model.kickSequencer
             in
-            ( { model | kickSequencer = { kickSequencer | steps = newSteps, editingStep = Nothing } }, updateKickSequence (List.map (\a -> transformKickStep model.kick a) newSteps) )
+            ( { model | kickSequencer = { kickSequencer | steps = newSteps, editingStep = Nothing } }, updateKickSequence (encodePattern newSteps) )
 
         MoveSnare value ->
             let
@@ -1047,7 +951,7 @@ update msg model =
                 snareSequencer =
                     model.snareSequencer
             in
-            ( { model | snareSequencer = { snareSequencer | steps = newSteps, editingStep = Nothing } }, updateSnareSequence (List.map (\a -> transformSnareStep model.snare a) newSteps) )
+            ( { model | snareSequencer = { snareSequencer | steps = newSteps, editingStep = Nothing } }, updateSnareSequence (encodePattern newSteps) )
 
         MoveHat value ->
             let
@@ -1073,7 +977,7 @@ update msg model =
                 hatSequencer =
                     model.hatSequencer
             in
-            ( { model | hatSequencer = { hatSequencer | steps = newSteps, editingStep = Nothing } }, updateHatSequence (List.map (\a -> transformHatStep model.hat a) newSteps) )
+            ( { model | hatSequencer = { hatSequencer | steps = newSteps, editingStep = Nothing } }, updateHatSequence (encodePattern newSteps) )
 
         ToggleKickEdit ->
             let